@@ -0,0 +1,83 @@
+//! Counts heap allocations made by `LruCache` under steady-state churn.
+//!
+//! Run with `cargo bench --bench alloc`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use rust_lru::LruCache;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static DEALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        DEALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const CAPACITY: usize = 1024;
+const OPERATIONS: u64 = 1_000_000;
+
+fn report(name: &str, f: impl FnOnce()) {
+    let allocs = ALLOCATIONS.load(Ordering::Relaxed);
+    let deallocs = DEALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+
+    f();
+
+    let elapsed = start.elapsed();
+    let allocs = ALLOCATIONS.load(Ordering::Relaxed) - allocs;
+    let deallocs = DEALLOCATIONS.load(Ordering::Relaxed) - deallocs;
+    println!(
+        "{:<24} {:>10} allocs {:>10} frees {:>8.3} allocs/op {:>8.1} ns/op",
+        name,
+        allocs,
+        deallocs,
+        allocs as f64 / OPERATIONS as f64,
+        elapsed.as_nanos() as f64 / OPERATIONS as f64,
+    );
+}
+
+fn main() {
+    let mut cache = LruCache::new(CAPACITY);
+    for i in 0..CAPACITY as u64 {
+        cache.insert(i, i);
+    }
+
+    // Every insert misses and evicts the least recently used entry.
+    report("insert (full, evicting)", || {
+        for i in 0..OPERATIONS {
+            cache.insert(black_box(CAPACITY as u64 + i), i);
+        }
+    });
+
+    // Remove an entry and insert a new one, exercising the free list.
+    report("remove + insert", || {
+        for i in 0..OPERATIONS {
+            let key = CAPACITY as u64 + OPERATIONS + i;
+            cache.remove(&(key - CAPACITY as u64));
+            cache.insert(black_box(key), i);
+        }
+    });
+
+    report("get (hit)", || {
+        let base = CAPACITY as u64 + 2 * OPERATIONS - CAPACITY as u64;
+        for i in 0..OPERATIONS {
+            black_box(cache.get(&(base + i % CAPACITY as u64)));
+        }
+    });
+}
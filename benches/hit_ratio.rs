@@ -0,0 +1,95 @@
+//! Compares hit ratios of the cache variants on Zipfian traffic.
+//!
+//! Run with `cargo bench --bench hit_ratio`.
+
+use rust_lru::{
+    ArcCache, LruCache, S3FifoCache, SieveCache, SlruCache, TinyLfuCache, TwoQueueCache,
+};
+
+const KEYS: usize = 100_000;
+const REQUESTS: usize = 1_000_000;
+const CAPACITIES: [usize; 3] = [100, 1_000, 10_000];
+const SKEWS: [f64; 2] = [0.8, 1.0];
+
+/// xorshift64*, enough randomness for a benchmark trace.
+struct Rng(u64);
+
+impl Rng {
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let bits = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11;
+        bits as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Draws keys in `0..n`, where key `k` has probability proportional to
+/// `1 / (k + 1)^skew`.
+struct Zipf {
+    cdf: Vec<f64>,
+}
+
+impl Zipf {
+    fn new(n: usize, skew: f64) -> Self {
+        let mut total = 0.0;
+        let mut cdf: Vec<f64> = (0..n)
+            .map(|k| {
+                total += 1.0 / ((k + 1) as f64).powf(skew);
+                total
+            })
+            .collect();
+        for p in &mut cdf {
+            *p /= total;
+        }
+        Zipf { cdf }
+    }
+
+    fn sample(&self, rng: &mut Rng) -> u64 {
+        let u = rng.next_f64();
+        self.cdf.partition_point(|&p| p < u).min(self.cdf.len() - 1) as u64
+    }
+}
+
+/// Replays `trace` as a read-through cache and returns the hit ratio.
+macro_rules! hit_ratio {
+    ($cache:expr, $trace:expr) => {{
+        let mut cache = $cache;
+        let mut hits = 0;
+        for &key in $trace {
+            if cache.get(&key).is_some() {
+                hits += 1;
+            } else {
+                cache.insert(key, key);
+            }
+        }
+        hits as f64 / $trace.len() as f64
+    }};
+}
+
+fn main() {
+    println!(
+        "{:>6} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "skew", "capacity", "lru", "slru", "2q", "arc", "sieve", "s3fifo", "tinylfu",
+    );
+    for skew in SKEWS {
+        let zipf = Zipf::new(KEYS, skew);
+        let mut rng = Rng(0x853c_49e6_748f_ea9b);
+        let trace: Vec<u64> = (0..REQUESTS).map(|_| zipf.sample(&mut rng)).collect();
+
+        for capacity in CAPACITIES {
+            println!(
+                "{:>6.1} {:>8} {:>8.4} {:>8.4} {:>8.4} {:>8.4} {:>8.4} {:>8.4} {:>8.4}",
+                skew,
+                capacity,
+                hit_ratio!(LruCache::new(capacity), &trace),
+                hit_ratio!(SlruCache::new(capacity), &trace),
+                hit_ratio!(TwoQueueCache::new(capacity), &trace),
+                hit_ratio!(ArcCache::new(capacity), &trace),
+                hit_ratio!(SieveCache::new(capacity), &trace),
+                hit_ratio!(S3FifoCache::new(capacity), &trace),
+                hit_ratio!(TinyLfuCache::new(capacity), &trace),
+            );
+        }
+    }
+}
@@ -0,0 +1,425 @@
+//! Adaptive replacement cache.
+
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+use crate::ghost::GhostList;
+use crate::list::{Links, List};
+use crate::store::{Keyed, Store};
+use crate::{Evicted, InsertResult, Node};
+
+// Set on entries in T2, the frequency list.
+const FREQUENT: u8 = 1;
+
+impl<K, V> Keyed for Node<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    fn entry(&self) -> Option<(&K, &V)> {
+        Some((&self.key, &self.value))
+    }
+
+    fn value_mut(&mut self) -> Option<&mut V> {
+        Some(&mut self.value)
+    }
+
+    fn into_entry(self) -> Option<(K, V)> {
+        Some((self.key, self.value))
+    }
+}
+
+/// A cache using the Adaptive Replacement Cache algorithm of Megiddo and
+/// Modha.
+///
+/// Entries seen once live in T1 and entries seen at least twice in T2. Keys
+/// recently evicted from either list are remembered in the ghost lists B1
+/// and B2. A miss that hits a ghost list shifts the target size of T1
+/// towards whichever list would have kept the key, so the cache adapts
+/// between recency and frequency.
+///
+/// Ghost hits are detected by [`insert`](Self::insert). A lookup that misses
+/// should be followed by an insert of the fetched value, as with any
+/// read-through cache.
+pub struct ArcCache<K, V> {
+    capacity: usize,
+    // The target length of T1, called `p` in the paper.
+    target: usize,
+    store: Store<Node<K, V>>,
+    t1: List,
+    t2: List,
+    b1: GhostList,
+    b2: GhostList,
+}
+
+impl<K, V> ArcCache<K, V>
+    where K: Hash + Eq
+{
+    pub fn new(capacity: usize) -> Self {
+        ArcCache {
+            capacity,
+            target: 0,
+            store: Store::with_capacity(capacity),
+            t1: List::default(),
+            t2: List::default(),
+            b1: GhostList::with_capacity(capacity),
+            b2: GhostList::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The current target length of the recency list T1. It grows on hits
+    /// in B1 and shrinks on hits in B2.
+    pub fn target(&self) -> usize {
+        self.target
+    }
+
+    /// Returns `true` if `key` is cached, without marking it as used.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.store.contains(key)
+    }
+
+    /// Looks up `key` without marking it as used.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.store.peek(key)
+    }
+
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.store.index_of(key)?;
+        self.access(index);
+        Some(self.store.value(index))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.store.index_of(key)?;
+        self.access(index);
+        Some(self.store.value_mut(index))
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> InsertResult<K, V> {
+        let hash = self.store.hash(&key);
+        let mut evicted = Evicted::new();
+
+        if let Some(index) = self.store.find(hash, &key) {
+            let replaced = std::mem::replace(self.store.value_mut(index), value);
+            self.access(index);
+            return InsertResult { replaced: Some(replaced), evicted };
+        }
+
+        if self.capacity == 0 {
+            evicted.push((key, value));
+            return InsertResult { replaced: None, evicted };
+        }
+
+        // Without removals a ghost hit implies a full cache, but `remove`
+        // can leave ghosts behind in a cache that still has room.
+        let full = self.len() >= self.capacity;
+        let (b1, b2) = (self.b1.len(), self.b2.len());
+        let frequent = if self.b1.remove(hash) {
+            self.target = (self.target + (b2 / b1).max(1)).min(self.capacity);
+            if full {
+                self.replace(false, &mut evicted);
+            }
+            true
+        } else if self.b2.remove(hash) {
+            self.target = self.target.saturating_sub((b1 / b2).max(1));
+            if full {
+                self.replace(true, &mut evicted);
+            }
+            true
+        } else {
+            if self.t1.len() + b1 >= self.capacity {
+                if self.t1.len() < self.capacity {
+                    self.b1.pop_back();
+                    if full {
+                        self.replace(false, &mut evicted);
+                    }
+                } else if let Some(index) = self.t1.tail() {
+                    // T1 holds the whole cache, drop its oldest entry
+                    // without remembering it.
+                    evicted.push(self.remove_index(index));
+                }
+            } else if self.len() + b1 + b2 >= self.capacity {
+                if self.len() + b1 + b2 >= 2 * self.capacity {
+                    self.b2.pop_back();
+                }
+                if full {
+                    self.replace(false, &mut evicted);
+                }
+            }
+            false
+        };
+
+        let index = self.store.insert(Node {
+            key,
+            value,
+            hash,
+            weight: 1,
+            flags: if frequent { FREQUENT } else { 0 },
+            links: Links::default(),
+        });
+        if frequent {
+            self.t2.push_front(&mut self.store.nodes, index);
+        } else {
+            self.t1.push_front(&mut self.store.nodes, index);
+        }
+        InsertResult { replaced: None, evicted }
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.store.index_of(key)?;
+        Some(self.remove_index(index))
+    }
+}
+
+impl<K, V> ArcCache<K, V> {
+    /// Moves a resident entry to the front of T2.
+    fn access(&mut self, index: u32) {
+        let entry = &mut self.store.nodes[index];
+        if entry.flags & FREQUENT == 0 {
+            entry.flags |= FREQUENT;
+            self.t1.unlink(&mut self.store.nodes, index);
+            self.t2.push_front(&mut self.store.nodes, index);
+        } else {
+            self.t2.move_to_front(&mut self.store.nodes, index);
+        }
+    }
+
+    /// Evicts the oldest entry of T1 or T2 into its ghost list, depending on
+    /// how T1 compares to its target. This is `REPLACE` in the paper.
+    fn replace(&mut self, in_b2: bool, evicted: &mut Evicted<K, V>) {
+        let t1 = self.t1.len();
+        let from_t1 = t1 > 0 && (t1 > self.target || (in_b2 && t1 == self.target));
+        let index = match (from_t1, self.t1.tail(), self.t2.tail()) {
+            (true, Some(index), _) | (false, _, Some(index)) | (false, Some(index), None) => index,
+            _ => return,
+        };
+
+        let hash = self.store.nodes[index].hash;
+        if self.store.nodes[index].flags & FREQUENT == 0 {
+            self.b1.push_front(hash);
+        } else {
+            self.b2.push_front(hash);
+        }
+        evicted.push(self.remove_index(index));
+    }
+
+    fn remove_index(&mut self, index: u32) -> (K, V) {
+        if self.store.nodes[index].flags & FREQUENT == 0 {
+            self.t1.unlink(&mut self.store.nodes, index);
+        } else {
+            self.t2.unlink(&mut self.store.nodes, index);
+        }
+        self.store.remove(index).into_entry().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    #[test]
+    fn test_insert_and_get() {
+        let mut cache: ArcCache<String, i32> = ArcCache::new(2);
+        assert_eq!(cache.get("a"), None);
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+        assert_eq!(cache.get("a"), Some(&1));
+        assert_eq!(cache.insert("a".to_string(), 3).replaced, Some(1));
+        *cache.get_mut("b").unwrap() += 10;
+        assert_eq!(cache.peek("b"), Some(&12));
+        assert_eq!(cache.len(), 2);
+
+        assert_eq!(cache.remove("a"), Some(3));
+        assert_eq!(cache.remove_entry("b"), Some(("b".to_string(), 12)));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_zero_capacity_hands_entry_back() {
+        let mut cache: ArcCache<u32, u32> = ArcCache::new(0);
+        let evicted = cache.insert(1, 1).evicted;
+        assert_eq!(evicted.into_vec(), [(1, 1)]);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_ghost_hits_adapt_target() {
+        let mut cache: ArcCache<&str, u32> = ArcCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.get("b");
+        let evicted = cache.insert("c", 3).evicted;
+        assert_eq!(evicted.into_vec(), [("a", 1)]);
+
+        // "a" comes back from B1: T1 should have been larger, so grow its
+        // target. "b" is evicted from T2 into B2.
+        let evicted = cache.insert("a", 1).evicted;
+        assert_eq!(evicted.into_vec(), [("b", 2)]);
+        assert_eq!(cache.target(), 1);
+
+        // "b" comes back from B2: shrink the target again.
+        let evicted = cache.insert("b", 2).evicted;
+        assert_eq!(evicted.into_vec(), [("c", 3)]);
+        assert_eq!(cache.target(), 0);
+        assert!(cache.contains("a"));
+        assert!(cache.contains("b"));
+    }
+
+    #[test]
+    fn test_frequent_entries_survive_scan() {
+        let mut cache: ArcCache<u32, u32> = ArcCache::new(4);
+        for key in [1, 2, 1, 2] {
+            if cache.get(&key).is_none() {
+                cache.insert(key, key);
+            }
+        }
+        for key in 100..200 {
+            cache.insert(key, key);
+        }
+        assert!(cache.contains(&1));
+        assert!(cache.contains(&2));
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn test_target_moves_by_ghost_list_ratio() {
+        let mut cache: ArcCache<u32, u32> = ArcCache::new(4);
+        for key in 1..=4 {
+            cache.insert(key, key);
+            cache.get(&key);
+        }
+        // With T1 empty, 5 pushes 1 from T2 into B2. 6 then pushes 5 from
+        // T1 into B1, and once used, 7 pushes 2 into B2 as well.
+        cache.insert(5, 5);
+        cache.insert(6, 6);
+        cache.get(&6);
+        cache.insert(7, 7);
+        assert_eq!((cache.b1.len(), cache.b2.len()), (1, 2));
+
+        // A hit in B1 grows the target by |B2| / |B1|, here 2. T1 is within
+        // it, so 3 goes from T2.
+        let evicted = cache.insert(5, 5).evicted;
+        assert_eq!(evicted.into_vec(), [(3, 3)]);
+        assert_eq!(cache.target(), 2);
+        assert_eq!((cache.b1.len(), cache.b2.len()), (0, 3));
+
+        // A hit in B2 shrinks it by at least 1. T1 is now at the target, so
+        // 7 goes into B1.
+        let evicted = cache.insert(1, 1).evicted;
+        assert_eq!(evicted.into_vec(), [(7, 7)]);
+        assert_eq!(cache.target(), 1);
+        assert_eq!((cache.t1.len(), cache.t2.len()), (0, 4));
+    }
+
+    #[test]
+    fn test_scan_plus_loop_beats_lru() {
+        // A loop over 60 keys, interleaved every other round with a scan of
+        // keys that are never seen again. The loop fits in the cache, the
+        // mix does not.
+        let mut scan = 1000;
+        let rounds = (0..20).map(|round| {
+            let mut trace = Vec::new();
+            for key in 0..60 {
+                trace.push(key);
+                if round % 2 == 0 {
+                    trace.extend([scan, scan + 1]);
+                    scan += 2;
+                }
+            }
+            trace
+        });
+
+        let mut arc = ArcCache::new(100);
+        let mut lru = LruCache::new(100);
+        let (mut arc_hits, mut lru_hits) = (0, 0);
+        let mut targets = Vec::new();
+        for (round, trace) in rounds.enumerate() {
+            let mut loop_hits = 0;
+            for key in trace {
+                match arc.get(&key) {
+                    Some(_) => {
+                        arc_hits += 1;
+                        loop_hits += (key < 60) as usize;
+                    }
+                    None => { arc.insert(key, ()); }
+                }
+                match lru.get(&key) {
+                    Some(_) => lru_hits += 1,
+                    None => { lru.insert(key, ()); }
+                }
+            }
+            targets.push(arc.target());
+            // Once the loop has been seen twice, the scans no longer touch it.
+            if round >= 3 {
+                assert_eq!(loop_hits, 60, "round {round}");
+            }
+        }
+
+        // T1 starts out holding everything, so the first ghost hits come in
+        // round 2, when loop keys evicted by the scan come back from B1 and
+        // grow the target. The loop then stays in T2 and nothing is evicted
+        // from it, so there are no B2 hits to move the target back.
+        assert_eq!(targets[..2], [0, 0]);
+        assert!(targets[2] > 0);
+        assert!(targets[2..].iter().all(|&target| target == targets[2]));
+
+        // The loop holds T2 and the rest of the cache goes to the newest
+        // scan keys. T1 stays longer than its target, so each scan miss
+        // evicts from T1 rather than from the loop.
+        assert_eq!((arc.t1.len(), arc.t2.len()), (40, 60));
+        assert!((0..60).all(|key| arc.store.index_of(&key).is_some_and(|index| {
+            arc.store.nodes[index].flags & FREQUENT != 0
+        })));
+        assert!(arc.target() < arc.t1.len());
+        assert_eq!(arc.b2.len(), 0);
+
+        assert!(arc_hits > lru_hits + 200, "arc: {arc_hits}, lru: {lru_hits}");
+    }
+
+    #[test]
+    fn test_remove_leaves_room() {
+        let mut cache: ArcCache<u32, u32> = ArcCache::new(2);
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        cache.insert(3, 3);
+        cache.remove(&3);
+
+        // 1 is a ghost, but the cache has room so nothing is evicted.
+        assert!(cache.insert(1, 1).evicted.is_empty());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.insert(4, 4).evicted.len(), 1);
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -0,0 +1,271 @@
+//! CLOCK cache with lookups through a shared reference.
+
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::store::{Keyed, Store};
+use crate::{Evicted, InsertResult};
+
+struct ClockEntry<K, V> {
+    key: K,
+    value: V,
+    hash: u64,
+    referenced: AtomicBool,
+}
+
+impl<K, V> Keyed for ClockEntry<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    fn entry(&self) -> Option<(&K, &V)> {
+        Some((&self.key, &self.value))
+    }
+
+    fn value_mut(&mut self) -> Option<&mut V> {
+        Some(&mut self.value)
+    }
+
+    fn into_entry(self) -> Option<(K, V)> {
+        Some((self.key, self.value))
+    }
+}
+
+/// A cache using the CLOCK algorithm, where a hit only sets an atomic
+/// reference bit instead of reordering entries. [`get`](Self::get) therefore
+/// takes `&self`, and the cache is `Sync` when its keys and values are, so
+/// readers can share it behind an `RwLock` read guard or an `Arc`.
+///
+/// Entries sit in a fixed ring of slots. To make room, a hand sweeps the
+/// ring, clearing reference bits as it goes, and evicts the first entry
+/// whose bit was already clear. [`Cache`](crate::Cache) with
+/// [`policy::Clock`](crate::policy::Clock) evicts in the same way but needs
+/// `&mut self` for every hit.
+pub struct ClockCache<K, V> {
+    capacity: usize,
+    hand: u32,
+    store: Store<ClockEntry<K, V>>,
+}
+
+impl<K, V> ClockCache<K, V>
+    where K: Hash + Eq
+{
+    pub fn new(capacity: usize) -> Self {
+        ClockCache {
+            capacity,
+            hand: 0,
+            store: Store::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if `key` is cached, without marking it as used.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.store.contains(key)
+    }
+
+    /// Looks up `key` without marking it as used.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.store.peek(key)
+    }
+
+    /// Looks up `key` and sets its reference bit.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let entry = &self.store.nodes[self.store.index_of(key)?];
+        // Skip the write when the bit is already set, to keep the cache
+        // line shared between readers.
+        if !entry.referenced.load(Ordering::Relaxed) {
+            entry.referenced.store(true, Ordering::Relaxed);
+        }
+        Some(&entry.value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.store.index_of(key)?;
+        let entry = &mut self.store.nodes[index];
+        *entry.referenced.get_mut() = true;
+        Some(&mut entry.value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> InsertResult<K, V> {
+        let hash = self.store.hash(&key);
+        let mut evicted = Evicted::new();
+
+        if let Some(index) = self.store.find(hash, &key) {
+            let entry = &mut self.store.nodes[index];
+            *entry.referenced.get_mut() = true;
+            let replaced = std::mem::replace(&mut entry.value, value);
+            return InsertResult { replaced: Some(replaced), evicted };
+        }
+
+        if self.capacity == 0 {
+            evicted.push((key, value));
+            return InsertResult { replaced: None, evicted };
+        }
+
+        if self.len() >= self.capacity {
+            let index = self.sweep();
+            evicted.push(self.remove_index(index));
+        }
+
+        // A freed slot is handed out again first, so an evicted entry's
+        // slot goes to its replacement, just behind the hand.
+        self.store.insert(ClockEntry {
+            key,
+            value,
+            hash,
+            referenced: AtomicBool::new(false),
+        });
+        InsertResult { replaced: None, evicted }
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.store.index_of(key)?;
+        Some(self.remove_index(index))
+    }
+}
+
+impl<K, V> ClockCache<K, V> {
+    /// Advances the hand to the next unreferenced entry, clearing the bits
+    /// of referenced ones along the way, and returns that entry's slot. The
+    /// hand is left just past it. Only called on a non-empty cache.
+    fn sweep(&mut self) -> u32 {
+        loop {
+            let index = self.hand;
+            self.hand += 1;
+            if self.hand as usize >= self.store.nodes.end() {
+                self.hand = 0;
+            }
+
+            if let Some(entry) = self.store.nodes.get(index) {
+                if !entry.referenced.swap(false, Ordering::Relaxed) {
+                    return index;
+                }
+            }
+        }
+    }
+
+    fn remove_index(&mut self, index: u32) -> (K, V) {
+        self.store.remove(index).into_entry().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::RwLock;
+
+    use super::*;
+
+    fn evicted<K, V>(result: InsertResult<K, V>) -> Vec<(K, V)> {
+        result.evicted.into_iter().collect()
+    }
+
+    #[test]
+    fn test_lookups_and_removal() {
+        let mut cache: ClockCache<String, i32> = ClockCache::new(2);
+        assert_eq!(cache.get("a"), None);
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+        assert_eq!(cache.insert("b".to_string(), 3).replaced, Some(2));
+        *cache.get_mut("b").unwrap() += 10;
+
+        // Peeking leaves the reference bit of "a" clear, so it goes first.
+        assert_eq!(cache.peek("a"), Some(&1));
+        assert!(cache.contains("a"));
+        assert_eq!(evicted(cache.insert("c".to_string(), 4)), [("a".to_string(), 1)]);
+
+        assert_eq!(cache.remove("b"), Some(13));
+        assert_eq!(cache.remove("b"), None);
+        assert_eq!(cache.remove_entry("c"), Some(("c".to_string(), 4)));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_zero_capacity_hands_entry_back() {
+        let mut cache: ClockCache<u32, u32> = ClockCache::new(0);
+        assert_eq!(evicted(cache.insert(1, 1)), [(1, 1)]);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_hand_skips_referenced_entries() {
+        let mut cache: ClockCache<u32, u32> = ClockCache::new(3);
+        for key in 1..=3 {
+            cache.insert(key, key);
+        }
+        cache.get(&1);
+        cache.get(&3);
+
+        // The hand clears 1, evicts 2 and stops in front of 3.
+        assert_eq!(evicted(cache.insert(4, 4)), [(2, 2)]);
+        // 3 gets its bit cleared, then 1 goes.
+        assert_eq!(evicted(cache.insert(5, 5)), [(1, 1)]);
+        // 4 took the slot of 2, which is next.
+        assert_eq!(evicted(cache.insert(6, 6)), [(4, 4)]);
+        assert!(cache.contains(&3));
+    }
+
+    #[test]
+    fn test_insert_after_remove_does_not_evict() {
+        let mut cache: ClockCache<u32, u32> = ClockCache::new(3);
+        for key in 1..=3 {
+            cache.insert(key, key);
+        }
+        cache.remove(&2);
+        assert!(cache.insert(4, 4).evicted.is_empty());
+        assert_eq!(cache.len(), 3);
+        assert_eq!(evicted(cache.insert(5, 5)), [(1, 1)]);
+    }
+
+    #[test]
+    fn test_shared_reads() {
+        let cache = RwLock::new(ClockCache::new(4));
+        for key in 0..4u32 {
+            cache.write().unwrap().insert(key, key * 10);
+        }
+
+        std::thread::scope(|scope| {
+            for key in 0..3u32 {
+                let cache = &cache;
+                scope.spawn(move || {
+                    let cache = cache.read().unwrap();
+                    assert_eq!(cache.get(&key), Some(&(key * 10)));
+                });
+            }
+        });
+
+        // Only 3 was never read.
+        assert_eq!(evicted(cache.write().unwrap().insert(4, 40)), [(3, 30)]);
+    }
+}
@@ -0,0 +1,196 @@
+//! `HashMap`-style entry API for [`Cache`].
+
+use std::hash::Hash;
+
+use crate::policy::{EvictionPolicy, Lru};
+use crate::{Cache, Evicted, InsertResult, UnitWeigher, Weigher};
+
+/// A view into a single cache slot, returned by [`Cache::entry`].
+pub enum Entry<'a, K, V, P = Lru, W = UnitWeigher> {
+    Occupied(OccupiedEntry<'a, K, V, P, W>),
+    Vacant(VacantEntry<'a, K, V, P, W>),
+}
+
+/// An entry for a key that is cached. It has already been marked as used.
+pub struct OccupiedEntry<'a, K, V, P = Lru, W = UnitWeigher> {
+    cache: &'a mut Cache<K, V, P, W>,
+    index: u32,
+}
+
+/// A value cached by [`VacantEntry::try_insert`].
+#[derive(Debug)]
+pub struct Inserted<'a, K, V> {
+    /// The cached value. It is not weighed again after being modified
+    /// through this reference.
+    pub value: &'a mut V,
+    /// The entries evicted to make room.
+    pub evicted: Evicted<K, V>,
+}
+
+/// An entry for a key that is not cached.
+pub struct VacantEntry<'a, K, V, P = Lru, W = UnitWeigher> {
+    cache: &'a mut Cache<K, V, P, W>,
+    hash: u64,
+    key: K,
+}
+
+impl<'a, K, V, P, W> Entry<'a, K, V, P, W>
+    where K: Hash + Eq, P: EvictionPolicy, W: Weigher<K, V>
+{
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Returns the cached value, inserting `default` first if the entry is
+    /// vacant. The value is weighed when it is inserted, but not again after
+    /// being modified through the returned reference; use
+    /// [`and_modify`](Self::and_modify) or [`Cache::get_mut_weighed`] for
+    /// that. The same goes for the other `or_insert` methods.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    /// Modifies an occupied entry in place and weighs it again. Entries
+    /// evicted to make it fit are dropped.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+            entry.cache.reweigh(entry.index);
+        }
+        self
+    }
+}
+
+impl<'a, K, V, P, W> Entry<'a, K, V, P, W>
+    where K: Hash + Eq, V: Default, P: EvictionPolicy, W: Weigher<K, V>
+{
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K, V, P, W> OccupiedEntry<'a, K, V, P, W>
+    where K: Hash + Eq, P: EvictionPolicy, W: Weigher<K, V>
+{
+    pub(crate) fn new(cache: &'a mut Cache<K, V, P, W>, index: u32) -> Self {
+        OccupiedEntry { cache, index }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.cache.entries[self.index].key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.cache.entries[self.index].value
+    }
+
+    /// Entries are not weighed again after being modified this way; use
+    /// [`Entry::and_modify`] or [`insert`](Self::insert) for that.
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.cache.entries[self.index].value
+    }
+
+    /// Like [`get_mut`](Self::get_mut), the entry is not weighed again
+    /// after being modified through the returned reference.
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.cache.entries[self.index].value
+    }
+
+    /// Replaces the value, returning the old one, and weighs the entry
+    /// again. Other entries are evicted if the cache no longer fits, and
+    /// dropped; use [`replace`](Self::replace) to get them back. As with
+    /// [`Cache::insert`], an entry that now weighs more than the capacity is
+    /// removed instead, which is why this consumes the entry.
+    pub fn insert(self, value: V) -> V {
+        self.replace(value).replaced.unwrap()
+    }
+
+    /// Like [`insert`](Self::insert), but also returns the entries evicted
+    /// to make the new value fit, or this entry if it cannot fit at all.
+    pub fn replace(self, value: V) -> InsertResult<K, V> {
+        let OccupiedEntry { cache, index } = self;
+        let replaced = std::mem::replace(&mut cache.entries[index].value, value);
+        let mut evicted = Evicted::new();
+        if cache.set_weight(index) > cache.capacity {
+            evicted.push(cache.remove_index(index));
+        } else {
+            cache.evict_to_fit(0, Some(index), &mut evicted);
+        }
+        InsertResult { replaced: Some(replaced), evicted }
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    pub fn remove_entry(self) -> (K, V) {
+        self.cache.remove_index(self.index)
+    }
+}
+
+impl<'a, K, V, P, W> VacantEntry<'a, K, V, P, W>
+    where K: Hash + Eq, P: EvictionPolicy, W: Weigher<K, V>
+{
+    pub(crate) fn new(cache: &'a mut Cache<K, V, P, W>, hash: u64, key: K) -> Self {
+        VacantEntry { cache, hash, key }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Caches `value`, evicting entries first until it fits. The evicted
+    /// entries are dropped; [`try_insert`](Self::try_insert) returns them.
+    ///
+    /// The new entry itself is never evicted here, since a reference to it
+    /// is returned. An entry that weighs more than the capacity pushes out
+    /// every other entry and is kept until the next insert evicts it, so a
+    /// cache with zero capacity holds the one entry most recently inserted
+    /// this way. Use [`try_insert`](Self::try_insert) to get such an entry
+    /// back instead.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let weight = self.cache.weigher.weight(&self.key, &value);
+        let ttl = self.cache.default_ttl;
+        let index = self.cache.insert_new(self.hash, self.key, value, weight, ttl, &mut Evicted::new());
+        &mut self.cache.entries[index].value
+    }
+
+    /// Caches `value`, evicting entries first until it fits, and returns it
+    /// along with the evicted entries. An entry that weighs more than the
+    /// capacity is not cached and is handed back as the error.
+    pub fn try_insert(self, value: V) -> Result<Inserted<'a, K, V>, (K, V)> {
+        let weight = self.cache.weigher.weight(&self.key, &value);
+        if weight > self.cache.capacity {
+            return Err((self.key, value));
+        }
+
+        let mut evicted = Evicted::new();
+        let ttl = self.cache.default_ttl;
+        let index = self.cache.insert_new(self.hash, self.key, value, weight, ttl, &mut evicted);
+        Ok(Inserted { value: &mut self.cache.entries[index].value, evicted })
+    }
+}
@@ -0,0 +1,100 @@
+//! Lists of recently evicted keys, for the adaptive caches.
+//!
+//! Ghost entries only remember the hash of an evicted key. That keeps them
+//! small and avoids cloning keys, at the cost of the odd false ghost hit
+//! when two keys share a 64-bit hash.
+
+use std::fmt;
+
+use crate::list::{Linked, Links, List};
+use crate::slab::Slab;
+use crate::table::Table;
+
+struct Ghost {
+    hash: u64,
+    links: Links,
+}
+
+impl Linked for Ghost {
+    fn links(&self) -> &Links {
+        &self.links
+    }
+
+    fn links_mut(&mut self) -> &mut Links {
+        &mut self.links
+    }
+}
+
+/// A FIFO of key hashes with constant-time lookup and removal.
+pub(crate) struct GhostList {
+    table: Table,
+    ghosts: Slab<Ghost>,
+    list: List,
+}
+
+impl fmt::Debug for GhostList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GhostList").field("len", &self.len()).finish()
+    }
+}
+
+impl GhostList {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        GhostList {
+            table: Table::with_capacity(capacity),
+            ghosts: Slab::with_capacity(capacity),
+            list: List::default(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    fn find(&self, hash: u64) -> Option<u32> {
+        self.table.find(hash, |index| self.ghosts[index].hash == hash)
+    }
+
+    /// Remembers `hash` as the newest ghost.
+    pub(crate) fn push_front(&mut self, hash: u64) {
+        if let Some(index) = self.find(hash) {
+            self.list.move_to_front(&mut self.ghosts, index);
+            return;
+        }
+
+        let index = self.ghosts.insert(Ghost { hash, links: Links::default() });
+        let ghosts = &self.ghosts;
+        self.table.insert(hash, index, |index| ghosts[index].hash);
+        self.list.push_front(&mut self.ghosts, index);
+    }
+
+    /// Forgets `hash`, returning whether it was present.
+    pub(crate) fn remove(&mut self, hash: u64) -> bool {
+        match self.find(hash) {
+            Some(index) => {
+                self.unlink(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.table.clear();
+        self.ghosts = Slab::with_capacity(0);
+        self.list = List::default();
+    }
+
+    /// Forgets the oldest ghost.
+    pub(crate) fn pop_back(&mut self) {
+        if let Some(index) = self.list.tail() {
+            self.unlink(index);
+        }
+    }
+
+    fn unlink(&mut self, index: u32) {
+        self.table.remove(self.ghosts[index].hash, index);
+        self.list.unlink(&mut self.ghosts, index);
+        self.ghosts.remove(index);
+    }
+}
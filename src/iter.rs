@@ -0,0 +1,329 @@
+//! Iterators over a [`Cache`](crate::Cache) in eviction-list order.
+//!
+//! For an [`LruCache`](crate::LruCache) every iterator walks from the most
+//! recently used entry to the least recently used one; use `.rev()` to start
+//! from the coldest entry. None of them changes the recency of the entries
+//! they visit.
+
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+
+use crate::list::{Links, List, NIL};
+use crate::slab::{RawSlab, Slab};
+use crate::Node;
+
+/// The unvisited part of the list, consumed from both ends.
+#[derive(Clone)]
+struct Cursor {
+    front: u32,
+    back: u32,
+    len: usize,
+}
+
+impl Cursor {
+    fn new(list: &List) -> Self {
+        Cursor {
+            front: list.head().unwrap_or(NIL),
+            back: list.tail().unwrap_or(NIL),
+            len: list.len(),
+        }
+    }
+
+    fn front(&self) -> Option<u32> {
+        (self.len > 0).then_some(self.front)
+    }
+
+    fn back(&self) -> Option<u32> {
+        (self.len > 0).then_some(self.back)
+    }
+
+    fn advance_front(&mut self, links: &Links) {
+        self.front = links.next;
+        self.len -= 1;
+    }
+
+    fn advance_back(&mut self, links: &Links) {
+        self.back = links.prev;
+        self.len -= 1;
+    }
+}
+
+/// Iterator over `(&K, &V)`, created by [`Cache::iter`](crate::Cache::iter).
+pub struct Iter<'a, K, V> {
+    entries: &'a Slab<Node<K, V>>,
+    cursor: Cursor,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    pub(crate) fn new(entries: &'a Slab<Node<K, V>>, list: &List) -> Self {
+        Iter { entries, cursor: Cursor::new(list) }
+    }
+}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Iter { entries: self.entries, cursor: self.cursor.clone() }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = &self.entries[self.cursor.front()?];
+        self.cursor.advance_front(&node.links);
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.cursor.len, Some(self.cursor.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = &self.entries[self.cursor.back()?];
+        self.cursor.advance_back(&node.links);
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+/// Iterator over `(&K, &mut V)`, created by [`Cache::iter_mut`](crate::Cache::iter_mut).
+pub struct IterMut<'a, K, V> {
+    entries: RawSlab<Node<K, V>>,
+    cursor: Cursor,
+    marker: PhantomData<&'a mut Slab<Node<K, V>>>,
+}
+
+impl<'a, K, V> IterMut<'a, K, V> {
+    pub(crate) fn new(entries: &'a mut Slab<Node<K, V>>, list: &List) -> Self {
+        IterMut { entries: entries.raw(), cursor: Cursor::new(list), marker: PhantomData }
+    }
+
+    fn node(&self, index: u32) -> &'a mut Node<K, V> {
+        // SAFETY: the iterator holds the only borrow of the slab for `'a`,
+        // and the cursor yields every index at most once.
+        unsafe { self.entries.get_mut(index) }
+    }
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.node(self.cursor.front()?);
+        self.cursor.advance_front(&node.links);
+        Some((&node.key, &mut node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.cursor.len, Some(self.cursor.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = self.node(self.cursor.back()?);
+        self.cursor.advance_back(&node.links);
+        Some((&node.key, &mut node.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> FusedIterator for IterMut<'_, K, V> {}
+
+// SAFETY: `IterMut` behaves like `&mut Slab<Node<K, V>>`.
+unsafe impl<K: Send, V: Send> Send for IterMut<'_, K, V> {}
+unsafe impl<K: Sync, V: Sync> Sync for IterMut<'_, K, V> {}
+
+/// Iterator over the keys, created by [`Cache::keys`](crate::Cache::keys).
+#[derive(Clone)]
+pub struct Keys<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Keys<'a, K, V> {
+    pub(crate) fn new(inner: Iter<'a, K, V>) -> Self {
+        Keys { inner }
+    }
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Keys<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<K, V> ExactSizeIterator for Keys<'_, K, V> {}
+
+impl<K, V> FusedIterator for Keys<'_, K, V> {}
+
+/// Iterator over the values, created by [`Cache::values`](crate::Cache::values).
+#[derive(Clone)]
+pub struct Values<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Values<'a, K, V> {
+    pub(crate) fn new(inner: Iter<'a, K, V>) -> Self {
+        Values { inner }
+    }
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Values<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value)| value)
+    }
+}
+
+impl<K, V> ExactSizeIterator for Values<'_, K, V> {}
+
+impl<K, V> FusedIterator for Values<'_, K, V> {}
+
+/// Iterator over mutable values, created by [`Cache::values_mut`](crate::Cache::values_mut).
+pub struct ValuesMut<'a, K, V> {
+    inner: IterMut<'a, K, V>,
+}
+
+impl<'a, K, V> ValuesMut<'a, K, V> {
+    pub(crate) fn new(inner: IterMut<'a, K, V>) -> Self {
+        ValuesMut { inner }
+    }
+}
+
+impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<&'a mut V> {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for ValuesMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value)| value)
+    }
+}
+
+impl<K, V> ExactSizeIterator for ValuesMut<'_, K, V> {}
+
+impl<K, V> FusedIterator for ValuesMut<'_, K, V> {}
+
+/// Takes the front or back entry off a list whose index has already been
+/// cleared.
+fn take<K, V>(index: Option<u32>, entries: &mut Slab<Node<K, V>>) -> Option<(K, V)> {
+    let node = entries.remove(index?);
+    Some((node.key, node.value))
+}
+
+/// Owning iterator over `(K, V)`, created by `Cache::into_iter`.
+pub struct IntoIter<K, V> {
+    entries: Slab<Node<K, V>>,
+    list: List,
+}
+
+impl<K, V> IntoIter<K, V> {
+    pub(crate) fn new(entries: Slab<Node<K, V>>, list: List) -> Self {
+        IntoIter { entries, list }
+    }
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        take(self.list.pop_front(&mut self.entries), &mut self.entries)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.len(), Some(self.list.len()))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<(K, V)> {
+        take(self.list.pop_back(&mut self.entries), &mut self.entries)
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V> FusedIterator for IntoIter<K, V> {}
+
+/// Draining iterator over `(K, V)`, created by [`Cache::drain`](crate::Cache::drain).
+///
+/// The cache is empty as soon as the iterator is created. Entries that are
+/// not consumed are dropped along with the iterator. If the iterator is
+/// leaked instead, so are they, but the cache stays consistent.
+pub struct Drain<'a, K, V> {
+    entries: &'a mut Slab<Node<K, V>>,
+    // Taken from the cache, so it no longer reaches the drained entries.
+    list: List,
+}
+
+impl<'a, K, V> Drain<'a, K, V> {
+    pub(crate) fn new(entries: &'a mut Slab<Node<K, V>>, list: List) -> Self {
+        Drain { entries, list }
+    }
+}
+
+impl<K, V> Iterator for Drain<'_, K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        take(self.list.pop_front(self.entries), self.entries)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.len(), Some(self.list.len()))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Drain<'_, K, V> {
+    fn next_back(&mut self) -> Option<(K, V)> {
+        take(self.list.pop_back(self.entries), self.entries)
+    }
+}
+
+impl<K, V> ExactSizeIterator for Drain<'_, K, V> {}
+
+impl<K, V> FusedIterator for Drain<'_, K, V> {}
+
+impl<K, V> Drop for Drain<'_, K, V> {
+    fn drop(&mut self) {
+        while self.next().is_some() {}
+    }
+}
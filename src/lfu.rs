@@ -0,0 +1,362 @@
+//! Least frequently used cache with constant-time operations.
+
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+use crate::list::{Linked, Links, List};
+use crate::slab::Slab;
+use crate::store::{Keyed, Store};
+use crate::{Evicted, InsertResult};
+
+struct LfuNode<K, V> {
+    key: K,
+    value: V,
+    hash: u64,
+    bucket: u32,
+    links: Links,
+}
+
+impl<K, V> Keyed for LfuNode<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    fn entry(&self) -> Option<(&K, &V)> {
+        Some((&self.key, &self.value))
+    }
+
+    fn value_mut(&mut self) -> Option<&mut V> {
+        Some(&mut self.value)
+    }
+
+    fn into_entry(self) -> Option<(K, V)> {
+        Some((self.key, self.value))
+    }
+}
+
+impl<K, V> Linked for LfuNode<K, V> {
+    fn links(&self) -> &Links {
+        &self.links
+    }
+
+    fn links_mut(&mut self) -> &mut Links {
+        &mut self.links
+    }
+}
+
+/// All entries used `count` times, most recently used first.
+struct Bucket {
+    count: usize,
+    entries: List,
+    links: Links,
+}
+
+impl Linked for Bucket {
+    fn links(&self) -> &Links {
+        &self.links
+    }
+
+    fn links_mut(&mut self) -> &mut Links {
+        &mut self.links
+    }
+}
+
+/// A cache that evicts the least frequently used entry, breaking ties by
+/// evicting the least recently used one.
+///
+/// Entries are grouped into buckets by use count, and the buckets form a
+/// list in increasing order of count, so lookups, insertions and evictions
+/// all take constant time. Inserting counts as the first use, and replacing
+/// a value counts as another.
+///
+/// Counts only ever grow, so an entry that was popular once can outstay
+/// entries that are popular now. Calling [`decay`](Self::decay) every so
+/// often halves all counts to let old popularity fade.
+pub struct LfuCache<K, V> {
+    capacity: usize,
+    store: Store<LfuNode<K, V>>,
+    buckets: Slab<Bucket>,
+    // Ordered from the lowest count to the highest.
+    order: List,
+}
+
+impl<K, V> LfuCache<K, V>
+    where K: Hash + Eq
+{
+    pub fn new(capacity: usize) -> Self {
+        LfuCache {
+            capacity,
+            store: Store::with_capacity(capacity),
+            buckets: Slab::with_capacity(0),
+            order: List::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if `key` is cached, without counting a use.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.store.contains(key)
+    }
+
+    /// Looks up `key` without counting a use.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.store.peek(key)
+    }
+
+    /// The use count of `key`, if it is cached.
+    pub fn frequency<Q>(&self, key: &Q) -> Option<usize>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.store.index_of(key)?;
+        Some(self.buckets[self.store.nodes[index].bucket].count)
+    }
+
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.store.index_of(key)?;
+        self.access(index);
+        Some(self.store.value(index))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.store.index_of(key)?;
+        self.access(index);
+        Some(self.store.value_mut(index))
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> InsertResult<K, V> {
+        let hash = self.store.hash(&key);
+        let mut evicted = Evicted::new();
+
+        if let Some(index) = self.store.find(hash, &key) {
+            let replaced = std::mem::replace(self.store.value_mut(index), value);
+            self.access(index);
+            return InsertResult { replaced: Some(replaced), evicted };
+        }
+
+        if self.capacity == 0 {
+            evicted.push((key, value));
+            return InsertResult { replaced: None, evicted };
+        }
+
+        if self.len() >= self.capacity {
+            let bucket = self.order.head().unwrap();
+            let index = self.buckets[bucket].entries.tail().unwrap();
+            evicted.push(self.remove_index(index));
+        }
+
+        let bucket = match self.order.head() {
+            Some(bucket) if self.buckets[bucket].count == 1 => bucket,
+            head => {
+                let bucket = self.new_bucket(1);
+                match head {
+                    Some(head) => self.order.insert_before(&mut self.buckets, bucket, head),
+                    None => self.order.push_front(&mut self.buckets, bucket),
+                }
+                bucket
+            }
+        };
+        let index = self.store.insert(LfuNode { key, value, hash, bucket, links: Links::default() });
+        self.buckets[bucket].entries.push_front(&mut self.store.nodes, index);
+        InsertResult { replaced: None, evicted }
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.store.index_of(key)?;
+        Some(self.remove_index(index))
+    }
+}
+
+impl<K, V> LfuCache<K, V> {
+    /// Halves every use count, rounding down but keeping it at least one.
+    /// Entries whose counts become equal keep their relative order, with
+    /// the formerly more frequent ones treated as more recently used. This
+    /// takes time linear in the number of entries.
+    pub fn decay(&mut self) {
+        let mut current = self.order.head();
+        let mut previous: Option<u32> = None;
+        while let Some(bucket) = current {
+            current = self.order.next(&self.buckets, bucket);
+            let count = (self.buckets[bucket].count / 2).max(1);
+
+            match previous {
+                Some(into) if self.buckets[into].count == count => {
+                    let mut from = std::mem::take(&mut self.buckets[bucket].entries);
+                    let into_entries = &mut self.buckets[into].entries;
+                    while let Some(index) = from.pop_back(&mut self.store.nodes) {
+                        self.store.nodes[index].bucket = into;
+                        into_entries.push_front(&mut self.store.nodes, index);
+                    }
+                    self.order.unlink(&mut self.buckets, bucket);
+                    self.buckets.remove(bucket);
+                }
+                _ => {
+                    self.buckets[bucket].count = count;
+                    previous = Some(bucket);
+                }
+            }
+        }
+    }
+
+    fn new_bucket(&mut self, count: usize) -> u32 {
+        self.buckets.insert(Bucket { count, entries: List::default(), links: Links::default() })
+    }
+
+    /// Moves an entry to the front of the bucket for one more use.
+    fn access(&mut self, index: u32) {
+        let bucket = self.store.nodes[index].bucket;
+        let count = self.buckets[bucket].count.saturating_add(1);
+
+        let next = match self.order.next(&self.buckets, bucket) {
+            Some(next) if self.buckets[next].count == count => next,
+            _ if self.buckets[bucket].entries.len() == 1 => {
+                // The entry is alone, so its bucket can be reused as is.
+                self.buckets[bucket].count = count;
+                return;
+            }
+            next => {
+                let new = self.new_bucket(count);
+                match next {
+                    Some(next) => self.order.insert_before(&mut self.buckets, new, next),
+                    None => self.order.push_back(&mut self.buckets, new),
+                }
+                new
+            }
+        };
+
+        self.unlink(index);
+        self.store.nodes[index].bucket = next;
+        self.buckets[next].entries.push_front(&mut self.store.nodes, index);
+    }
+
+    /// Takes an entry out of its bucket, dropping the bucket if it empties.
+    fn unlink(&mut self, index: u32) {
+        let bucket = self.store.nodes[index].bucket;
+        self.buckets[bucket].entries.unlink(&mut self.store.nodes, index);
+        if self.buckets[bucket].entries.len() == 0 {
+            self.order.unlink(&mut self.buckets, bucket);
+            self.buckets.remove(bucket);
+        }
+    }
+
+    fn remove_index(&mut self, index: u32) -> (K, V) {
+        self.unlink(index);
+        self.store.remove(index).into_entry().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evicted<K, V>(result: InsertResult<K, V>) -> Vec<(K, V)> {
+        result.evicted.into_iter().collect()
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut cache: LfuCache<String, i32> = LfuCache::new(2);
+        assert_eq!(cache.get("a"), None);
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+        assert_eq!(cache.get("a"), Some(&1));
+        assert_eq!(cache.insert("a".to_string(), 3).replaced, Some(1));
+        assert_eq!(cache.frequency("a"), Some(3));
+        *cache.get_mut("b").unwrap() += 10;
+        // Peeking does not count.
+        assert_eq!(cache.peek("b"), Some(&12));
+        assert!(cache.contains("b"));
+        assert_eq!(cache.frequency("b"), Some(2));
+
+        assert_eq!(cache.remove("a"), Some(3));
+        assert_eq!(cache.remove_entry("b"), Some(("b".to_string(), 12)));
+        assert!(cache.is_empty());
+        assert_eq!(cache.frequency("a"), None);
+    }
+
+    #[test]
+    fn test_zero_capacity_hands_entry_back() {
+        let mut cache: LfuCache<u32, u32> = LfuCache::new(0);
+        assert_eq!(evicted(cache.insert(1, 1)), [(1, 1)]);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_evicts_least_frequent_then_least_recent() {
+        let mut cache: LfuCache<u32, u32> = LfuCache::new(3);
+        for key in 1..=3 {
+            cache.insert(key, key);
+        }
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&2);
+        cache.get(&3);
+
+        // 2 and 3 tie, and 2 was used longer ago.
+        assert_eq!(evicted(cache.insert(4, 4)), [(2, 2)]);
+        // The newcomer is the least frequent now.
+        assert_eq!(evicted(cache.insert(5, 5)), [(4, 4)]);
+        cache.get(&5);
+        cache.get(&5);
+        assert_eq!(evicted(cache.insert(6, 6)), [(3, 3)]);
+        assert!(cache.contains(&1));
+        assert!(cache.contains(&5));
+    }
+
+    #[test]
+    fn test_decay_lets_old_favourites_go() {
+        let mut cache: LfuCache<u32, u32> = LfuCache::new(3);
+        cache.insert(1, 1);
+        for _ in 0..7 {
+            cache.get(&1);
+        }
+        cache.insert(2, 2);
+        cache.get(&2);
+        cache.get(&2);
+        cache.insert(3, 3);
+
+        cache.decay();
+        assert_eq!(cache.frequency(&1), Some(4));
+        assert_eq!(cache.frequency(&2), Some(1));
+        assert_eq!(cache.frequency(&3), Some(1));
+        // 2 and 3 now share a bucket, with 2 counted as more recent.
+        assert_eq!(evicted(cache.insert(4, 4)), [(3, 3)]);
+
+        for _ in 0..3 {
+            cache.decay();
+        }
+        assert_eq!(cache.frequency(&1), Some(1));
+        cache.get(&2);
+        cache.get(&4);
+        assert_eq!(evicted(cache.insert(5, 5)), [(1, 1)]);
+    }
+}
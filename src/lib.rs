@@ -1,138 +1,832 @@
 use std::borrow::Borrow;
-use std::ptr::NonNull;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
 
-struct Entry<K, V> {
+use list::{Linked, Links, List, NIL};
+use policy::{EvictionPolicy, Lru, Order, S3Fifo, Sieve, Slot, Slru, TinyLfu, TwoQueue};
+use slab::Slab;
+use table::Table;
+
+pub use arc::ArcCache;
+pub use clock::ClockCache;
+pub use entry::{Entry, Inserted, OccupiedEntry, VacantEntry};
+pub use iter::{Drain, IntoIter, Iter, IterMut, Keys, Values, ValuesMut};
+pub use lfu::LfuCache;
+pub use lirs::LirsCache;
+pub use time::{Clock, MockClock, SystemClock};
+pub use weigher::{UnitWeigher, ValueMut, Weigher};
+
+pub mod policy;
+
+mod arc;
+mod clock;
+mod entry;
+mod ghost;
+mod iter;
+mod lfu;
+mod lirs;
+mod list;
+mod sketch;
+mod slab;
+mod store;
+mod table;
+mod time;
+mod weigher;
+
+struct Node<K, V> {
     key: K,
     value: V,
-    next: *mut Entry<K, V>,
-    prev: *mut Entry<K, V>,
+    hash: u64,
+    weight: usize,
+    // Owned by the eviction policy.
+    flags: u8,
+    links: Links,
 }
 
-pub struct LruCache<K, V> {
-    capacity: usize,
-    cache: std::collections::HashMap<K, NonNull<Entry<K, V>>>,
-    head: *mut Entry<K, V>,
-    tail: *mut Entry<K, V>,
+impl<K, V> Linked for Node<K, V> {
+    fn links(&self) -> &Links {
+        &self.links
+    }
+
+    fn links_mut(&mut self) -> &mut Links {
+        &mut self.links
+    }
+}
+
+/// When an entry expires, kept next to the nodes rather than in them so
+/// caches that never expire anything pay nothing per entry.
+#[derive(Clone, Copy, Default)]
+struct Expiry {
+    // From the time to live, and from the time to idle.
+    ttl: Option<Instant>,
+    idle: Option<Instant>,
+}
+
+impl Expiry {
+    fn is_expired_at(&self, now: Instant) -> bool {
+        [self.ttl, self.idle].into_iter().flatten().any(|deadline| deadline <= now)
+    }
+}
+
+/// What a call to [`Cache::insert`] pushed out of the cache.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InsertResult<K, V> {
+    /// The previous value, if the key was already cached.
+    pub replaced: Option<V>,
+    /// The entries evicted to make room. An entry that cannot be cached at
+    /// all, because it weighs more than the capacity, is handed back here
+    /// as well.
+    pub evicted: Evicted<K, V>,
+}
+
+/// Entries evicted by a single operation, least recently used first.
+///
+/// The first entry is stored inline, so the common case of evicting a single
+/// entry does not allocate.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Evicted<K, V> {
+    first: Option<(K, V)>,
+    rest: Vec<(K, V)>,
+}
+
+impl<K, V> Evicted<K, V> {
+    fn new() -> Self {
+        Evicted { first: None, rest: Vec::new() }
+    }
+
+    fn push(&mut self, entry: (K, V)) {
+        if self.first.is_none() {
+            self.first = Some(entry);
+        } else {
+            self.rest.push(entry);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.first.iter().len() + self.rest.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.first.is_none()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(K, V)> {
+        self.first.iter().chain(self.rest.iter())
+    }
 }
 
-impl <K, V> Drop for LruCache<K, V> {
-    fn drop(&mut self) {
-        let mut current = self.head;
-        while !current.is_null() {
-            let next = unsafe { (*current).next };
+#[cfg(test)]
+impl<K, V> Evicted<K, V> {
+    fn into_vec(self) -> Vec<(K, V)> {
+        self.into_iter().collect()
+    }
+}
+
+impl<K, V> Default for Evicted<K, V> {
+    fn default() -> Self {
+        Evicted::new()
+    }
+}
+
+impl<K, V> IntoIterator for Evicted<K, V> {
+    type Item = (K, V);
+    type IntoIter = std::iter::Chain<std::option::IntoIter<(K, V)>, std::vec::IntoIter<(K, V)>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.first.into_iter().chain(self.rest)
+    }
+}
 
-            drop(unsafe {
-                Box::from_raw(current)
-            });
+/// Lookup counts of a cache, see [`Cache::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+}
 
-            current = next;
+impl Stats {
+    /// The fraction of lookups that hit, or zero before the first lookup.
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
         }
     }
 }
 
-impl <K, V> LruCache<K, V>
-    where K: std::hash::Hash + std::cmp::Eq + Clone
+/// A cache bounded by the total weight of its entries, evicting whichever
+/// entry its [`EvictionPolicy`] picks once that weight exceeds the capacity.
+///
+/// With the default [`UnitWeigher`] every entry weighs one, so the capacity
+/// is simply the maximum number of entries.
+pub struct Cache<K, V, P = Lru, W = UnitWeigher> {
+    capacity: usize,
+    weight: usize,
+    policy: P,
+    weigher: W,
+    // Whether storage is kept sized to `capacity`. Only true for unit
+    // weights, where the capacity is an entry count.
+    presized: bool,
+    stats: Stats,
+    default_ttl: Option<Duration>,
+    time_to_idle: Option<Duration>,
+    clock: Box<dyn Clock + Send + Sync>,
+    table: Table,
+    entries: Slab<Node<K, V>>,
+    // Indexed by slot. Empty until an entry gets a deadline.
+    expiry: Vec<Expiry>,
+    list: List,
+}
+
+/// A cache that evicts the least recently used entries first.
+pub type LruCache<K, V, W = UnitWeigher> = Cache<K, V, Lru, W>;
+
+/// A segmented LRU cache, see [`Slru`].
+pub type SlruCache<K, V, W = UnitWeigher> = Cache<K, V, Slru, W>;
+
+/// A 2Q cache, see [`TwoQueue`].
+pub type TwoQueueCache<K, V, W = UnitWeigher> = Cache<K, V, TwoQueue, W>;
+
+/// A SIEVE cache, see [`Sieve`].
+pub type SieveCache<K, V, W = UnitWeigher> = Cache<K, V, Sieve, W>;
+
+/// An S3-FIFO cache, see [`S3Fifo`].
+pub type S3FifoCache<K, V, W = UnitWeigher> = Cache<K, V, S3Fifo, W>;
+
+/// A W-TinyLFU cache, see [`TinyLfu`].
+pub type TinyLfuCache<K, V, W = UnitWeigher> = Cache<K, V, TinyLfu, W>;
+
+impl <K, V, P> Cache<K, V, P>
+    where K: Hash + Eq, P: EvictionPolicy + Default
 {
     pub fn new(capacity: usize) -> Self {
-        LruCache {
+        Cache::with_policy(capacity, P::default())
+    }
+}
+
+impl <K, V, P> Cache<K, V, P>
+    where K: Hash + Eq, P: EvictionPolicy
+{
+    pub fn with_policy(capacity: usize, mut policy: P) -> Self {
+        policy.on_resize(capacity);
+        Cache {
+            capacity,
+            weight: 0,
+            policy,
+            weigher: UnitWeigher,
+            presized: true,
+            stats: Stats::default(),
+            default_ttl: None,
+            time_to_idle: None,
+            clock: Box::new(SystemClock),
+            table: Table::with_capacity(capacity),
+            entries: Slab::with_capacity(capacity),
+            expiry: Vec::new(),
+            list: List::default(),
+        }
+    }
+}
+
+impl <K, V, P, W> Cache<K, V, P, W>
+    where K: Hash + Eq, P: EvictionPolicy + Default, W: Weigher<K, V>
+{
+    /// Creates a cache bounded by the total weight of its entries, as
+    /// measured by `weigher`, instead of their number.
+    pub fn with_weigher(capacity: usize, weigher: W) -> Self {
+        Cache::with_policy_and_weigher(capacity, P::default(), weigher)
+    }
+}
+
+impl <K, V, P, W> Cache<K, V, P, W>
+    where K: Hash + Eq, P: EvictionPolicy, W: Weigher<K, V>
+{
+    pub fn with_policy_and_weigher(capacity: usize, mut policy: P, weigher: W) -> Self {
+        policy.on_resize(capacity);
+        Cache {
             capacity,
-            cache: std::collections::HashMap::with_capacity(capacity),
-            head: std::ptr::null_mut(),
-            tail: std::ptr::null_mut(),
+            weight: 0,
+            policy,
+            weigher,
+            presized: false,
+            stats: Stats::default(),
+            default_ttl: None,
+            time_to_idle: None,
+            clock: Box::new(SystemClock),
+            table: Table::with_capacity(0),
+            entries: Slab::with_capacity(0),
+            expiry: Vec::new(),
+            list: List::default(),
         }
     }
 
+    /// The maximum total weight, which is the maximum number of entries
+    /// unless a custom weigher is used.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of cached entries. This includes expired entries that have
+    /// not been removed yet; they are removed when a lookup finds them, when
+    /// they are evicted, or by [`remove_expired`](Self::remove_expired).
     pub fn len(&self) -> usize {
-        self.cache.len()
+        self.table.len()
     }
 
-    fn remove_entry(&mut self, entry: NonNull<Entry<K, V>>) {
-        let prev = unsafe { entry.as_ref().prev };
-        let next = unsafe { entry.as_ref().next };
+    /// Whether the cache holds no entries, expired or not.
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
 
-        if !prev.is_null() {
-            unsafe { (*prev).next = next };
-        } else {
-            self.head = next;
+    /// The total weight of all cached entries.
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    /// Hits and misses of the lookups that mark entries as used: `get`,
+    /// `get_mut`, `get_mut_weighed` and `entry`. Peeking is not counted.
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+
+    pub fn default_ttl(&self) -> Option<Duration> {
+        self.default_ttl
+    }
+
+    /// Sets the time to live of entries added without an explicit one, by
+    /// [`insert`](Self::insert) or [`entry`](Self::entry). Entries already
+    /// cached keep their deadline.
+    pub fn set_default_ttl(&mut self, ttl: Option<Duration>) {
+        self.default_ttl = ttl;
+    }
+
+    pub fn time_to_idle(&self) -> Option<Duration> {
+        self.time_to_idle
+    }
+
+    /// Makes entries expire once they go unused for `tti`, on top of any
+    /// time to live. Inserting an entry or using it through
+    /// [`get`](Self::get), [`get_mut`](Self::get_mut), [`entry`](Self::entry)
+    /// or [`insert`](Self::insert) restarts its idle time; peeking does not.
+    /// Entries already cached keep their idle deadline until next used. A
+    /// `tti` too long to add to the current time never runs out.
+    pub fn set_time_to_idle(&mut self, tti: Option<Duration>) {
+        self.time_to_idle = tti;
+    }
+
+    /// Replaces the [`SystemClock`] that expiration deadlines are read from,
+    /// for instance with a [`MockClock`] in tests. Deadlines already set are
+    /// kept and compared against the new clock.
+    pub fn set_clock(&mut self, clock: impl Clock + Send + Sync + 'static) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Removes every expired entry, returning them in the policy's order.
+    /// This walks the whole cache.
+    pub fn remove_expired(&mut self) -> Vec<(K, V)> {
+        if self.expiry.is_empty() {
+            return Vec::new();
+        }
+
+        let now = self.clock.now();
+        let mut expired = Vec::new();
+        let mut current = self.list.head();
+        while let Some(index) = current {
+            current = self.list.next(&self.entries, index);
+            if self.expiry(index).is_expired_at(now) {
+                expired.push(index);
+            }
+        }
+        expired.into_iter().map(|index| self.remove_index(index)).collect()
+    }
+
+    /// Iterates over the entries in the policy's order, without changing
+    /// their recency. For an [`LruCache`] that is from the most to the least
+    /// recently used entry.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(&self.entries, &self.list)
+    }
+
+    /// Like [`iter`](Self::iter), but with mutable values. Entries are not
+    /// weighed again after being modified this way.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut::new(&mut self.entries, &self.list)
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys::new(self.iter())
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values::new(self.iter())
+    }
+
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut::new(self.iter_mut())
+    }
+
+    /// Changes the capacity, evicting entries until the cache fits. The
+    /// evicted entries are returned in eviction order. With unit weights,
+    /// internal storage is also grown or shrunk to match the new capacity.
+    pub fn resize(&mut self, capacity: usize) -> Evicted<K, V> {
+        let mut evicted = Evicted::new();
+        while self.weight > capacity {
+            let index = self.victim();
+            evicted.push(self.remove_index(index));
         }
 
-        if !next.is_null() {
-            unsafe { (*next).prev = prev };
+        if capacity != self.capacity {
+            self.policy.on_resize(capacity);
+        }
+        self.capacity = capacity;
+        if !self.presized {
+            // The capacity says nothing about the number of entries.
+        } else if capacity < self.entries.capacity() {
+            self.compact(capacity);
         } else {
-            self.tail = prev;
+            let entries = &self.entries;
+            self.table.reserve(capacity - self.len(), |index| entries[index].hash);
+            self.entries.grow_to(capacity);
+        }
+        evicted
+    }
+
+    /// Shrinks internal storage to hold at least `min_capacity` entries, or
+    /// the current length if that is larger. The capacity is unchanged, so
+    /// the storage grows again as the cache fills up.
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        let min_capacity = min_capacity.max(self.len());
+        if min_capacity < self.entries.capacity() {
+            self.compact(min_capacity);
         }
     }
 
-    fn push_entry_front(&mut self, mut entry: NonNull<Entry<K, V>>) {
-        unsafe {
-            entry.as_mut().next = self.head;
-            entry.as_mut().prev = std::ptr::null_mut();
+    /// Moves every entry into freshly allocated storage of `capacity` slots,
+    /// keeping their order.
+    fn compact(&mut self, capacity: usize) {
+        let mut entries = Slab::with_capacity(capacity);
+        let mut list = List::default();
+        let mut relocated = vec![NIL; self.entries.end()];
+        while let Some(from) = self.list.pop_back(&mut self.entries) {
+            let to = entries.insert(self.entries.remove(from));
+            list.push_front(&mut entries, to);
+            relocated[from as usize] = to;
+        }
+        self.policy.on_relocate(|slot| Slot(relocated[slot.index()]));
+        if !self.expiry.is_empty() {
+            let mut expiry = vec![Expiry::default(); entries.end()];
+            for (from, &to) in relocated.iter().enumerate() {
+                if let Some(&deadline) = self.expiry.get(from).filter(|_| to != NIL) {
+                    expiry[to as usize] = deadline;
+                }
+            }
+            self.expiry = expiry;
+        }
+        self.entries = entries;
+        self.list = list;
+
+        let entries = &self.entries;
+        self.table.clear();
+        let mut current = self.list.head();
+        while let Some(index) = current {
+            self.table.insert(entries[index].hash, index, |index| entries[index].hash);
+            current = self.list.next(entries, index);
+        }
+        self.table.shrink_to(capacity, |index| entries[index].hash);
+    }
+
+    /// Removes every entry, yielding them in the policy's order. The cache
+    /// keeps its capacity and can be reused right away.
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        self.table.clear();
+        self.weight = 0;
+        self.expiry.clear();
+        self.policy.on_clear();
+        Drain::new(&mut self.entries, std::mem::take(&mut self.list))
+    }
+
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<u32>
+        where K: Borrow<Q>, Q: Eq + ?Sized,
+    {
+        self.table.find(hash, |index| self.entries[index].key.borrow() == key)
+    }
+
+    fn index_of<Q>(&self, key: &Q) -> Option<u32>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.find(self.table.hash(key), key)
+    }
+
+    /// Like `index_of`, but skips an expired entry.
+    fn live_index_of<Q>(&self, key: &Q) -> Option<u32>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.index_of(key).filter(|&index| !self.is_expired(index))
+    }
+
+    /// Returns `true` if `key` is cached and not expired, without marking it
+    /// as used.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.live_index_of(key).is_some()
+    }
+
+    /// Looks up `key` without marking it as used. An expired entry is not
+    /// returned, but is left in place.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.live_index_of(key).map(|index| &self.entries[index].value)
+    }
+
+    /// Looks up `key` for modification without marking it as used. The
+    /// entry is weighed again once the returned guard is dropped. Caches with
+    /// unit weights can use [`peek_mut`](Self::peek_mut) instead.
+    pub fn peek_mut_weighed<Q>(&mut self, key: &Q) -> Option<ValueMut<'_, K, V, P, W>>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.live_index_of(key)?;
+        Some(ValueMut::new(self, index))
+    }
+
+    /// Looks up `key` and marks it as used. An expired entry is removed and
+    /// counts as a miss.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.touch(key)?;
+        Some(&self.entries[index].value)
+    }
+
+    /// Looks up `key` for modification and marks it as used. The entry is
+    /// weighed again once the returned guard is dropped. Caches with unit
+    /// weights can use [`get_mut`](Self::get_mut) instead.
+    pub fn get_mut_weighed<Q>(&mut self, key: &Q) -> Option<ValueMut<'_, K, V, P, W>>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.touch(key)?;
+        Some(ValueMut::new(self, index))
+    }
+
+    fn touch<Q>(&mut self, key: &Q) -> Option<u32>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let Some(index) = self.index_of(key).filter(|&index| !self.remove_if_expired(index)) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        self.access(index);
+        Some(index)
+    }
+
+    /// Caches `value` under `key` with the default time to live, if any.
+    pub fn insert(&mut self, key: K, value: V) -> InsertResult<K, V> {
+        self.insert_expiring(key, value, self.default_ttl)
+    }
+
+    /// Caches `value` under `key` until `ttl` has passed. Replacing the
+    /// value later starts a new time to live. A `ttl` too long to add to the
+    /// current time, like [`Duration::MAX`], never runs out.
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> InsertResult<K, V> {
+        self.insert_expiring(key, value, Some(ttl))
+    }
+
+    fn insert_expiring(&mut self, key: K, value: V, ttl: Option<Duration>) -> InsertResult<K, V> {
+        let hash = self.table.hash(&key);
+        let weight = self.weigher.weight(&key, &value);
+        let mut evicted = Evicted::new();
+
+        let found = self.find(hash, &key);
+        if let Some(index) = found.filter(|&index| !self.remove_if_expired(index)) {
+            let entry = &mut self.entries[index];
+            let replaced = std::mem::replace(&mut entry.value, value);
+            self.weight = self.weight - entry.weight + weight;
+            entry.weight = weight;
+            self.set_ttl(index, ttl);
+
+            if weight > self.capacity {
+                evicted.push(self.remove_index(index));
+            } else {
+                self.access(index);
+                self.evict_to_fit(0, Some(index), &mut evicted);
+            }
+            return InsertResult { replaced: Some(replaced), evicted };
         }
 
-        if !self.head.is_null() {
-            unsafe { (*self.head).prev = entry.as_ptr() };
+        if weight > self.capacity {
+            // An entry kept over capacity by `VacantEntry::insert` goes now.
+            self.evict_to_fit(0, None, &mut evicted);
+            evicted.push((key, value));
         } else {
-            self.tail = entry.as_ptr();
+            self.insert_new(hash, key, value, weight, ttl, &mut evicted);
         }
+        InsertResult { replaced: None, evicted }
+    }
 
-        self.head = entry.as_ptr();
+    /// Gets the entry for `key` for in-place manipulation. An occupied entry
+    /// is marked as used, and an expired one is removed first.
+    ///
+    /// Filling a vacant entry with `or_insert` and friends keeps a value
+    /// heavier than the capacity until the next insert evicts it;
+    /// [`VacantEntry::try_insert`] hands it back instead.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, P, W> {
+        let hash = self.table.hash(&key);
+        match self.find(hash, &key).filter(|&index| !self.remove_if_expired(index)) {
+            Some(index) => {
+                self.stats.hits += 1;
+                self.access(index);
+                Entry::Occupied(OccupiedEntry::new(self, index))
+            }
+            None => {
+                self.stats.misses += 1;
+                Entry::Vacant(VacantEntry::new(self, hash, key))
+            }
+        }
     }
 
-    fn get_free_entry(&mut self, key: K, value: V) -> NonNull<Entry<K, V>> {
-        let entry = Box::into_raw(Box::new(Entry {
+    /// Adds a key that is known to be missing, evicting entries first until
+    /// it fits. The last evicted slot is reused for the new entry.
+    fn insert_new(
+        &mut self,
+        hash: u64,
+        key: K,
+        value: V,
+        weight: usize,
+        ttl: Option<Duration>,
+        evicted: &mut Evicted<K, V>,
+    ) -> u32 {
+        self.evict_to_fit(weight, None, evicted);
+
+        let index = self.entries.insert(Node {
             key,
             value,
-            next: std::ptr::null_mut(),
-            prev: std::ptr::null_mut(),
-        }));
-        NonNull::new(entry).unwrap()
+            hash,
+            weight,
+            flags: 0,
+            links: Links::default(),
+        });
+        self.set_ttl(index, ttl);
+        self.set_idle(index, self.time_to_idle);
+        let entries = &self.entries;
+        self.table.insert(hash, index, |index| entries[index].hash);
+        self.list.push_front(&mut self.entries, index);
+        self.weight += weight;
+        self.policy.on_insert(&mut Order::new(&mut self.entries, &mut self.list), Slot(index));
+        index
     }
 
-    fn free_entry(&mut self, entry: NonNull<Entry<K, V>>) {
-        drop(unsafe {
-            Box::from_raw(entry.as_ptr())
-        });
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
     }
 
-    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
-        where K: Borrow<Q>, Q: std::hash::Hash + Eq + ?Sized,
+    /// Removes `key` and returns its entry. An expired entry is removed too,
+    /// but not returned.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
     {
+        let index = self.index_of(key)?;
+        let expired = self.is_expired(index);
+        let entry = self.remove_index(index);
+        (!expired).then_some(entry)
+    }
+}
 
-        if let Some(entry) = self.cache.get(&key).copied() {
-            self.remove_entry(entry);
-            self.push_entry_front(entry);
-            unsafe { Some(&entry.as_ref().value) }
-        } else {
-            None
+impl<K, V, P> Cache<K, V, P>
+    where K: Hash + Eq, P: EvictionPolicy
+{
+    /// Looks up `key` for modification without marking it as used. Every
+    /// entry weighs one, so modifying it never evicts anything.
+    pub fn peek_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.live_index_of(key)?;
+        Some(&mut self.entries[index].value)
+    }
+
+    /// Looks up `key` for modification and marks it as used.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.touch(key)?;
+        Some(&mut self.entries[index].value)
+    }
+}
+
+impl<K, V, W> Cache<K, V, Lru, W>
+    where W: Weigher<K, V>
+{
+    /// Removes entries that went idle for longer than the
+    /// [time to idle](Self::set_time_to_idle), returning them from the least
+    /// recently used one.
+    ///
+    /// Entries are kept in order of last use, so idle deadlines only grow
+    /// toward the front and the sweep stops at the first entry still in
+    /// use. That takes time proportional to the number of entries removed,
+    /// unlike [`remove_expired`](Self::remove_expired). After the time to
+    /// idle is shortened or enabled, entries behind the first live one may
+    /// be left for lookups to remove.
+    pub fn remove_idle(&mut self) -> Vec<(K, V)> {
+        let now = self.clock.now();
+        let mut idle = Vec::new();
+        while let Some(index) = self.list.tail() {
+            if self.expiry(index).idle.is_none_or(|deadline| deadline > now) {
+                break;
+            }
+            idle.push(self.remove_index(index));
         }
+        idle
+    }
+}
+
+impl<K, V, P, W> Cache<K, V, P, W>
+    where P: EvictionPolicy, W: Weigher<K, V>
+{
+    /// The deadline for an entry with `ttl` to live from now. The clock is
+    /// only read when there is a deadline to set, and a deadline too far off
+    /// for an `Instant` means the entry never expires.
+    fn deadline(&self, ttl: Option<Duration>) -> Option<Instant> {
+        ttl.and_then(|ttl| self.clock.now().checked_add(ttl))
     }
 
-    pub fn insert(&mut self, key: K, value: V) {
-        let entry = self.get_free_entry(key.clone(), value);
-        self.push_entry_front(entry);
+    fn expiry(&self, index: u32) -> Expiry {
+        self.expiry.get(index as usize).copied().unwrap_or_default()
+    }
 
-        if let Some(old_entry) = self.cache.insert(key, entry) {
-            self.remove_entry(old_entry);
-            self.free_entry(old_entry);
+    /// The expiry of the entry in `index`, growing the side table to cover
+    /// every slot if needed.
+    fn expiry_mut(&mut self, index: u32) -> &mut Expiry {
+        if index as usize >= self.expiry.len() {
+            self.expiry.resize(self.entries.end(), Expiry::default());
         }
-        else if self.cache.len() > self.capacity {
-            let entry = self.tail;
+        &mut self.expiry[index as usize]
+    }
 
-            if let Some(entry) = NonNull::new(entry) {
-                self.remove_entry(entry);
-                self.cache.remove(unsafe { &entry.as_ref().key });
-                self.free_entry(entry);
-            }
+    /// Gives the entry `ttl` to live from now, or no time to live.
+    fn set_ttl(&mut self, index: u32, ttl: Option<Duration>) {
+        let deadline = self.deadline(ttl);
+        if deadline.is_some() || (index as usize) < self.expiry.len() {
+            self.expiry_mut(index).ttl = deadline;
         }
     }
 
-    pub fn remove<Q>(&mut self, key: &Q)
-        where K: Borrow<Q>, Q: std::hash::Hash + Eq + ?Sized,
-    {
-        if let Some(entry) = self.cache.remove(key) {
-            self.remove_entry(entry);
-            self.free_entry(entry);
+    /// Gives the entry `tti` to be idle from now, or no time to idle.
+    fn set_idle(&mut self, index: u32, tti: Option<Duration>) {
+        let deadline = self.deadline(tti);
+        if deadline.is_some() || (index as usize) < self.expiry.len() {
+            self.expiry_mut(index).idle = deadline;
+        }
+    }
+
+    fn is_expired(&self, index: u32) -> bool {
+        let expiry = self.expiry(index);
+        (expiry.ttl.is_some() || expiry.idle.is_some()) && expiry.is_expired_at(self.clock.now())
+    }
+
+    /// Removes the entry if it has expired, returning whether it did.
+    fn remove_if_expired(&mut self, index: u32) -> bool {
+        let expired = self.is_expired(index);
+        if expired {
+            self.remove_index(index);
         }
+        expired
+    }
+
+    fn access(&mut self, index: u32) {
+        if self.time_to_idle.is_some() {
+            self.set_idle(index, self.time_to_idle);
+        }
+        self.policy.on_access(&mut Order::new(&mut self.entries, &mut self.list), Slot(index));
+    }
+
+    fn victim(&mut self) -> u32 {
+        self.policy.victim(&mut Order::new(&mut self.entries, &mut self.list)).0
+    }
+
+    /// Evicts entries until `incoming` more weight fits. Stops early if the
+    /// cache runs out of entries other than `keep`.
+    fn evict_to_fit(&mut self, incoming: usize, keep: Option<u32>, evicted: &mut Evicted<K, V>) {
+        let kept = keep.is_some() as usize;
+        while self.weight + incoming > self.capacity && self.table.len() > kept {
+            let mut order = Order::new(&mut self.entries, &mut self.list);
+            let index = match keep {
+                Some(keep) => self.policy.victim_except(&mut order, Slot(keep)).0,
+                None => self.policy.victim(&mut order).0,
+            };
+            evicted.push(self.remove_index(index));
+        }
+    }
+
+    /// Weighs an entry again after its value was modified in place, evicting
+    /// other entries until the cache fits.
+    fn reweigh(&mut self, index: u32) -> Evicted<K, V> {
+        self.set_weight(index);
+        let mut evicted = Evicted::new();
+        self.evict_to_fit(0, Some(index), &mut evicted);
+        evicted
+    }
+
+    /// Weighs an entry again and returns its new weight, without evicting.
+    fn set_weight(&mut self, index: u32) -> usize {
+        let entry = &mut self.entries[index];
+        let weight = self.weigher.weight(&entry.key, &entry.value);
+        self.weight = self.weight - entry.weight + weight;
+        entry.weight = weight;
+        weight
+    }
+
+    fn remove_index(&mut self, index: u32) -> (K, V) {
+        self.policy.on_remove(&mut Order::new(&mut self.entries, &mut self.list), Slot(index));
+        self.table.remove(self.entries[index].hash, index);
+        self.list.unlink(&mut self.entries, index);
+        if let Some(expiry) = self.expiry.get_mut(index as usize) {
+            *expiry = Expiry::default();
+        }
+        let entry = self.entries.remove(index);
+        self.weight -= entry.weight;
+        (entry.key, entry.value)
+    }
+}
+
+impl<'a, K, V, P, W> IntoIterator for &'a Cache<K, V, P, W>
+    where K: Hash + Eq, P: EvictionPolicy, W: Weigher<K, V>
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, P, W> IntoIterator for &'a mut Cache<K, V, P, W>
+    where K: Hash + Eq, P: EvictionPolicy, W: Weigher<K, V>
+{
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, P, W> IntoIterator for Cache<K, V, P, W> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    /// Consumes the cache, yielding entries in the policy's order.
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter::new(self.entries, self.list)
     }
 }
 
@@ -221,4 +915,805 @@ mod tests {
 
         assert_eq!(counter.load(std::sync::atomic::Ordering::SeqCst), 2);
     }
+
+    #[test]
+    fn test_insert_existing_replaces_value() {
+        let mut cache: LruCache<String, i32> = LruCache::new(2);
+        cache.insert("old".to_string(), 123);
+        cache.insert("test".to_string(), 42);
+        cache.insert("old".to_string(), 7);
+        assert_eq!(cache.len(), 2);
+
+        cache.insert("new".to_string(), 13);
+        assert_eq!(cache.get("test"), None);
+        assert_eq!(cache.get("old"), Some(&7));
+        assert_eq!(cache.get("new"), Some(&13));
+    }
+
+    #[test]
+    fn test_churn_reuses_entries() {
+        let mut cache: LruCache<u32, u32> = LruCache::new(3);
+        for i in 0..100 {
+            cache.insert(i, i * 2);
+        }
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(&96), None);
+        assert_eq!(cache.get(&97), Some(&194));
+        assert_eq!(cache.get(&98), Some(&196));
+        assert_eq!(cache.get(&99), Some(&198));
+
+        cache.remove(&97);
+        cache.remove(&98);
+        assert_eq!(cache.entries.vacant(), 2);
+        cache.insert(1, 1);
+        assert_eq!(cache.entries.vacant(), 1);
+        assert_eq!(cache.get(&1), Some(&1));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_drop_after_remove_and_reuse() {
+        let counter = Arc::new(AtomicIsize::new(0));
+
+        struct Droppy(Arc<AtomicIsize>);
+
+        impl Drop for Droppy {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, atomic::Ordering::SeqCst);
+            }
+        }
+
+        {
+            let mut cache: LruCache<u32, Droppy> = LruCache::new(2);
+            cache.insert(1, Droppy(counter.clone()));
+            cache.insert(2, Droppy(counter.clone()));
+            cache.remove(&1);
+            assert_eq!(counter.load(atomic::Ordering::SeqCst), 1);
+
+            cache.insert(3, Droppy(counter.clone()));
+            cache.insert(4, Droppy(counter.clone()));
+            assert_eq!(counter.load(atomic::Ordering::SeqCst), 2);
+
+            cache.remove(&4);
+            assert_eq!(counter.load(atomic::Ordering::SeqCst), 3);
+        }
+
+        assert_eq!(counter.load(atomic::Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_insert_reports_replaced_and_evicted() {
+        let mut cache: LruCache<String, i32> = LruCache::new(2);
+        assert_eq!(
+            cache.insert("a".to_string(), 1),
+            InsertResult { replaced: None, evicted: Evicted::new() },
+        );
+        cache.insert("b".to_string(), 2);
+        assert_eq!(
+            cache.insert("a".to_string(), 3),
+            InsertResult { replaced: Some(1), evicted: Evicted::new() },
+        );
+
+        let result = cache.insert("c".to_string(), 4);
+        assert_eq!(result.replaced, None);
+        assert_eq!(result.evicted.into_iter().collect::<Vec<_>>(), [("b".to_string(), 2)]);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_insert_with_zero_capacity_hands_entry_back() {
+        let mut cache: LruCache<String, i32> = LruCache::new(0);
+        let evicted = cache.insert("test".to_string(), 42).evicted;
+        assert_eq!(evicted.iter().collect::<Vec<_>>(), [&("test".to_string(), 42)]);
+    }
+
+    #[test]
+    fn test_remove_returns_value() {
+        let mut cache: LruCache<String, Vec<u8>> = LruCache::new(2);
+        cache.insert("a".to_string(), vec![1, 2, 3]);
+        cache.insert("b".to_string(), vec![4]);
+        assert_eq!(cache.remove("a"), Some(vec![1, 2, 3]));
+        assert_eq!(cache.remove("a"), None);
+        assert_eq!(cache.remove_entry("b"), Some(("b".to_string(), vec![4])));
+        assert_eq!(cache.remove_entry("b"), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_peek_does_not_promote() {
+        let mut cache: LruCache<String, i32> = LruCache::new(2);
+        cache.insert("old".to_string(), 123);
+        cache.insert("test".to_string(), 42);
+        assert_eq!(cache.peek("old"), Some(&123));
+        assert!(cache.contains("old"));
+        *cache.peek_mut("old").unwrap() += 1;
+
+        cache.insert("new".to_string(), 13);
+        assert!(!cache.contains("old"));
+        assert_eq!(cache.peek("old"), None);
+        assert!(cache.peek_mut("old").is_none());
+        assert_eq!(cache.peek("test"), Some(&42));
+    }
+
+    #[test]
+    fn test_peek_mut_updates_value() {
+        let mut cache: LruCache<String, i32> = LruCache::new(2);
+        cache.insert("test".to_string(), 42);
+        *cache.peek_mut("test").unwrap() = 7;
+        assert_eq!(cache.get("test"), Some(&7));
+    }
+
+    #[test]
+    fn test_get_mut_updates_and_promotes() {
+        let mut cache: LruCache<String, i32> = LruCache::new(2);
+        cache.insert("old".to_string(), 123);
+        cache.insert("test".to_string(), 42);
+        *cache.get_mut("old").unwrap() += 1;
+        assert!(cache.get_mut("missing").is_none());
+
+        cache.insert("new".to_string(), 13);
+        assert_eq!(cache.get("test"), None);
+        assert_eq!(cache.get("old"), Some(&124));
+    }
+
+    #[test]
+    fn test_entry_or_insert_with() {
+        let mut cache: LruCache<String, i32> = LruCache::new(2);
+        let mut calls = 0;
+        for _ in 0..3 {
+            *cache.entry("a".to_string()).or_insert_with(|| { calls += 1; 0 }) += 1;
+        }
+        assert_eq!(calls, 1);
+        assert_eq!(cache.peek("a"), Some(&3));
+        assert_eq!(*cache.entry("b".to_string()).or_default(), 0);
+        assert_eq!(*cache.entry("b".to_string()).or_insert(5), 0);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_entry_promotes_and_evicts() {
+        let mut cache: LruCache<String, i32> = LruCache::new(2);
+        cache.insert("old".to_string(), 123);
+        cache.insert("test".to_string(), 42);
+        cache.entry("old".to_string()).and_modify(|v| *v += 1).or_insert(0);
+
+        cache.entry("new".to_string()).or_insert(13);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("test"), None);
+        assert_eq!(cache.get("old"), Some(&124));
+        assert_eq!(cache.get("new"), Some(&13));
+    }
+
+    #[test]
+    fn test_occupied_entry_remove_and_replace() {
+        let mut cache: LruCache<String, i32> = LruCache::new(2);
+        cache.insert("a".to_string(), 1);
+        match cache.entry("a".to_string()) {
+            Entry::Occupied(entry) => {
+                assert_eq!(entry.key(), "a");
+                assert_eq!(entry.insert(2), 1);
+            }
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        match cache.entry("a".to_string()) {
+            Entry::Occupied(entry) => assert_eq!(entry.remove_entry(), ("a".to_string(), 2)),
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        assert!(cache.is_empty());
+
+        match cache.entry("b".to_string()) {
+            Entry::Occupied(_) => panic!("expected a vacant entry"),
+            Entry::Vacant(entry) => assert_eq!(entry.into_key(), "b"),
+        }
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_vacant_try_insert_with_zero_capacity() {
+        let mut cache: LruCache<String, i32> = LruCache::new(0);
+        let Entry::Vacant(entry) = cache.entry("a".to_string()) else { panic!() };
+        assert_eq!(entry.try_insert(1).unwrap_err(), ("a".to_string(), 1));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_vacant_insert_with_zero_capacity() {
+        let mut cache: LruCache<String, i32> = LruCache::new(0);
+        *cache.entry("a".to_string()).or_insert(1) += 1;
+        assert_eq!(cache.peek("a"), Some(&2));
+
+        cache.entry("b".to_string()).or_insert(3);
+        assert_eq!(cache.peek("a"), None);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.insert("c".to_string(), 4).evicted.into_vec(), [
+            ("b".to_string(), 3),
+            ("c".to_string(), 4),
+        ]);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_vacant_try_insert_reports_evicted() {
+        let mut cache: LruCache<String, i32> = LruCache::new(1);
+        cache.insert("a".to_string(), 1);
+        let Entry::Vacant(entry) = cache.entry("b".to_string()) else { panic!() };
+        let Inserted { value, evicted } = entry.try_insert(2).unwrap();
+        *value += 1;
+        assert_eq!(evicted.into_iter().collect::<Vec<_>>(), [("a".to_string(), 1)]);
+        assert_eq!(cache.peek("b"), Some(&3));
+    }
+
+    #[test]
+    fn test_iter_in_recency_order() {
+        let mut cache: LruCache<String, i32> = LruCache::new(3);
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+        cache.insert("c".to_string(), 3);
+        cache.get("a");
+
+        let keys: Vec<_> = cache.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["a", "c", "b"]);
+        let values: Vec<_> = cache.values().rev().copied().collect();
+        assert_eq!(values, [2, 3, 1]);
+
+        let mut iter = cache.iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next(), Some((&"a".to_string(), &1)));
+        assert_eq!(iter.next_back(), Some((&"b".to_string(), &2)));
+        assert_eq!(iter.len(), 1);
+        assert_eq!(iter.next(), Some((&"c".to_string(), &3)));
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(iter.next(), None);
+
+        // Iterating does not touch recency.
+        cache.insert("d".to_string(), 4);
+        assert!(!cache.contains("b"));
+    }
+
+    #[test]
+    fn test_iter_mut_and_values_mut() {
+        let mut cache: LruCache<String, i32> = LruCache::new(3);
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+        cache.insert("c".to_string(), 3);
+
+        for (key, value) in &mut cache {
+            if key == "b" {
+                *value *= 10;
+            }
+        }
+        for value in cache.values_mut().rev().take(1) {
+            *value += 100;
+        }
+
+        let entries: Vec<_> = (&cache).into_iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        assert_eq!(entries, [("c", 3), ("b", 20), ("a", 101)]);
+    }
+
+    #[test]
+    fn test_into_iter_owned() {
+        let mut cache: LruCache<String, i32> = LruCache::new(3);
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+        cache.insert("c".to_string(), 3);
+
+        let mut iter = cache.into_iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next_back(), Some(("a".to_string(), 1)));
+        let rest: Vec<_> = iter.collect();
+        assert_eq!(rest, [("c".to_string(), 3), ("b".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_drain_empties_cache() {
+        let mut cache: LruCache<String, i32> = LruCache::new(3);
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+        cache.insert("c".to_string(), 3);
+        cache.get("a");
+
+        let drained: Vec<_> = cache.drain().collect();
+        assert_eq!(drained, [
+            ("a".to_string(), 1),
+            ("c".to_string(), 3),
+            ("b".to_string(), 2),
+        ]);
+        assert!(cache.is_empty());
+        assert_eq!(cache.get("a"), None);
+
+        cache.insert("d".to_string(), 4);
+        assert_eq!(cache.get("d"), Some(&4));
+        assert_eq!(cache.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_partial_drain_drops_the_rest() {
+        let counter = Arc::new(AtomicIsize::new(0));
+
+        struct Droppy(Arc<AtomicIsize>);
+
+        impl Drop for Droppy {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, atomic::Ordering::SeqCst);
+            }
+        }
+
+        let mut cache: LruCache<u32, Droppy> = LruCache::new(3);
+        for i in 0..3 {
+            cache.insert(i, Droppy(counter.clone()));
+        }
+
+        let (key, _) = cache.drain().next_back().unwrap();
+        assert_eq!(key, 0);
+        assert_eq!(counter.load(atomic::Ordering::SeqCst), 3);
+        assert!(cache.is_empty());
+        assert_eq!(cache.iter().len(), 0);
+    }
+
+    #[test]
+    fn test_leaked_drain_leaves_cache_consistent() {
+        let mut cache: LruCache<u32, u32> = LruCache::new(2);
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        std::mem::forget(cache.drain());
+        assert!(cache.is_empty());
+        assert_eq!(cache.iter().len(), 0);
+
+        for i in 3..6 {
+            cache.insert(i, i);
+        }
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [5, 4]);
+    }
+
+    #[test]
+    fn test_resize_evicts_least_recent() {
+        let mut cache: LruCache<u32, u32> = LruCache::new(4);
+        for i in 0..4 {
+            cache.insert(i, i * 10);
+        }
+        cache.get(&0);
+        assert_eq!(cache.capacity(), 4);
+
+        assert_eq!(cache.resize(2).into_vec(), [(1, 10), (2, 20)]);
+        assert_eq!(cache.capacity(), 2);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.entries.capacity() <= 2);
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [0, 3]);
+        assert_eq!(cache.get(&3), Some(&30));
+
+        cache.insert(4, 40);
+        assert_eq!(cache.peek(&0), None);
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [4, 3]);
+    }
+
+    #[test]
+    fn test_resize_grow() {
+        let mut cache: LruCache<u32, u32> = LruCache::new(1);
+        cache.insert(1, 1);
+        assert!(cache.resize(3).is_empty());
+        assert!(cache.entries.capacity() >= 3);
+        cache.insert(2, 2);
+        cache.insert(3, 3);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.insert(4, 4).evicted.into_iter().collect::<Vec<_>>(), [(1, 1)]);
+
+        assert_eq!(cache.resize(0).len(), 3);
+        assert!(cache.is_empty());
+        assert_eq!(cache.insert(5, 5).evicted.into_iter().collect::<Vec<_>>(), [(5, 5)]);
+    }
+
+    #[test]
+    fn test_shrink_to_keeps_entries() {
+        let mut cache: LruCache<u32, u32> = LruCache::new(100);
+        for i in 0..10 {
+            cache.insert(i, i);
+        }
+        for i in 0..5 {
+            cache.remove(&(i * 2));
+        }
+        cache.shrink_to(0);
+        assert_eq!(cache.capacity(), 100);
+        assert!(cache.entries.capacity() < 100);
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [9, 7, 5, 3, 1]);
+        assert_eq!(cache.get(&5), Some(&5));
+        assert_eq!(cache.get(&4), None);
+
+        for i in 10..200 {
+            cache.insert(i, i);
+        }
+        assert_eq!(cache.len(), 100);
+    }
+
+    struct ByLen;
+
+    impl Weigher<&str, String> for ByLen {
+        fn weight(&self, _key: &&str, value: &String) -> usize {
+            value.len()
+        }
+    }
+
+    #[test]
+    fn test_weighted_evicts_until_fits() {
+        let mut cache = LruCache::with_weigher(10, ByLen);
+        cache.insert("a", "xxxx".to_string());
+        cache.insert("b", "xxx".to_string());
+        cache.insert("c", "xx".to_string());
+        assert_eq!(cache.weight(), 9);
+        assert_eq!(cache.len(), 3);
+
+        let evicted = cache.insert("d", "xxxxxxx".to_string()).evicted;
+        assert_eq!(evicted.len(), 2);
+        assert_eq!(
+            evicted.into_iter().map(|(key, _)| key).collect::<Vec<_>>(),
+            ["a", "b"],
+        );
+        assert_eq!(cache.weight(), 9);
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["d", "c"]);
+
+        cache.remove("c");
+        assert_eq!(cache.weight(), 7);
+    }
+
+    #[test]
+    fn test_weighted_rejects_oversized() {
+        let mut cache = LruCache::with_weigher(5, ByLen);
+        cache.insert("a", "xx".to_string());
+
+        let result = cache.insert("b", "xxxxxx".to_string());
+        assert_eq!(result.evicted.iter().collect::<Vec<_>>(), [&("b", "xxxxxx".to_string())]);
+        assert_eq!(cache.weight(), 2);
+        assert!(cache.contains("a"));
+
+        let result = cache.insert("a", "xxxxxx".to_string());
+        assert_eq!(result.replaced, Some("xx".to_string()));
+        assert_eq!(result.evicted.iter().collect::<Vec<_>>(), [&("a", "xxxxxx".to_string())]);
+        assert!(cache.is_empty());
+        assert_eq!(cache.weight(), 0);
+    }
+
+    #[test]
+    fn test_weighted_replace_updates_weight() {
+        let mut cache = LruCache::with_weigher(6, ByLen);
+        cache.insert("a", "xx".to_string());
+        cache.insert("b", "xx".to_string());
+        cache.insert("c", "xx".to_string());
+
+        let result = cache.insert("c", "xxxx".to_string());
+        assert_eq!(result.replaced, Some("xx".to_string()));
+        assert_eq!(result.evicted.into_iter().collect::<Vec<_>>(), [("a", "xx".to_string())]);
+        assert_eq!(cache.weight(), 6);
+    }
+
+    #[test]
+    fn test_get_mut_reweighs_on_drop() {
+        let mut cache = LruCache::with_weigher(6, ByLen);
+        cache.insert("a", "x".to_string());
+        cache.insert("b", "x".to_string());
+        cache.insert("c", "x".to_string());
+
+        cache.get_mut_weighed("a").unwrap().push_str("xxx");
+        assert_eq!(cache.weight(), 6);
+
+        cache.peek_mut_weighed("c").unwrap().push('x');
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["a", "c"]);
+        assert_eq!(cache.weight(), 6);
+
+        // The modified entry is kept even when it no longer fits on its own.
+        cache.get_mut_weighed("c").unwrap().push_str("xxxxx");
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["c"]);
+        assert_eq!(cache.weight(), 7);
+
+        cache.insert("d", "x".to_string());
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["d"]);
+        assert_eq!(cache.weight(), 1);
+    }
+
+    #[test]
+    fn test_reweighing_reports_evicted() {
+        let mut cache = LruCache::with_weigher(4, ByLen);
+        cache.insert("a", "x".to_string());
+        cache.insert("b", "x".to_string());
+        cache.insert("c", "x".to_string());
+
+        let mut value = cache.get_mut_weighed("c").unwrap();
+        value.push_str("xx");
+        assert_eq!(value.commit().into_iter().collect::<Vec<_>>(), [("a", "x".to_string())]);
+        assert_eq!(cache.weight(), 4);
+
+        let Entry::Occupied(entry) = cache.entry("b") else { panic!() };
+        let result = entry.replace("xxx".to_string());
+        assert_eq!(result.replaced, Some("x".to_string()));
+        assert_eq!(result.evicted.into_iter().collect::<Vec<_>>(), [("c", "xxx".to_string())]);
+        assert_eq!(cache.weight(), 3);
+    }
+
+    #[test]
+    fn test_occupied_replace_hands_back_oversized_entry() {
+        let mut cache = LruCache::with_weigher(4, ByLen);
+        cache.insert("a", "x".to_string());
+        cache.insert("b", "x".to_string());
+
+        // Like `insert`, the entry goes and the others stay.
+        let Entry::Occupied(entry) = cache.entry("b") else { panic!() };
+        let result = entry.replace("xxxxx".to_string());
+        assert_eq!(result.replaced, Some("x".to_string()));
+        assert_eq!(result.evicted.into_vec(), [("b", "xxxxx".to_string())]);
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["a"]);
+        assert_eq!(cache.weight(), 1);
+
+        let Entry::Occupied(entry) = cache.entry("a") else { panic!() };
+        assert_eq!(entry.insert("xxxxx".to_string()), "x");
+        assert!(cache.is_empty());
+        assert_eq!(cache.weight(), 0);
+    }
+
+    #[test]
+    fn test_entry_and_modify_reweighs() {
+        let mut cache = LruCache::with_weigher(4, ByLen);
+        cache.insert("a", "x".to_string());
+        cache.insert("b", "x".to_string());
+        cache.entry("b").and_modify(|v| v.push_str("xx")).or_default();
+        assert_eq!(cache.weight(), 4);
+
+        cache.entry("b").and_modify(|v| v.push('x')).or_default();
+        assert_eq!(cache.weight(), 4);
+        assert!(!cache.contains("a"));
+
+        cache.drain();
+        assert_eq!(cache.weight(), 0);
+    }
+
+    #[test]
+    fn test_closure_weigher() {
+        let mut cache = LruCache::with_weigher(8, |_: &u32, value: &Vec<u8>| value.len());
+        cache.insert(1, vec![0; 4]);
+        cache.insert(2, vec![0; 4]);
+        cache.insert(3, vec![0; 1]);
+        assert!(!cache.contains(&1));
+        assert_eq!(cache.weight(), 5);
+    }
+
+    #[test]
+    fn test_stats_count_lookups() {
+        let mut cache: LruCache<u32, u32> = LruCache::new(2);
+        assert_eq!(cache.stats().hit_ratio(), 0.0);
+        cache.insert(1, 1);
+        cache.get(&1);
+        cache.get(&2);
+        cache.get_mut(&1);
+        cache.entry(3).or_insert(3);
+        cache.peek(&1);
+        assert_eq!(cache.stats(), Stats { hits: 2, misses: 2 });
+        assert_eq!(cache.stats().hit_ratio(), 0.5);
+
+        cache.reset_stats();
+        assert_eq!(cache.stats(), Stats::default());
+    }
+
+    #[test]
+    fn test_keys_without_clone() {
+        #[derive(Hash, PartialEq, Eq)]
+        struct Key(String);
+
+        impl Borrow<str> for Key {
+            fn borrow(&self) -> &str {
+                &self.0
+            }
+        }
+
+        let mut cache: LruCache<Key, i32> = LruCache::new(2);
+        cache.insert(Key("a".to_string()), 1);
+        cache.insert(Key("b".to_string()), 2);
+        cache.insert(Key("a".to_string()), 3);
+        cache.insert(Key("c".to_string()), 4);
+        assert_eq!(cache.get("a"), Some(&3));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("c"), Some(&4));
+
+        cache.remove("a");
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_expired_entries_are_misses() {
+        let mut cache: LruCache<u32, u32> = LruCache::new(3);
+        cache.insert_with_ttl(1, 10, Duration::ZERO);
+        cache.insert_with_ttl(2, 20, Duration::from_secs(3600));
+        cache.insert(3, 30);
+
+        // Expired entries still count until something removes them.
+        assert_eq!(cache.len(), 3);
+        assert!(!cache.contains(&1));
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.len(), 3);
+
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats(), Stats { hits: 0, misses: 1 });
+        assert_eq!(cache.get(&2), Some(&20));
+        assert_eq!(cache.get(&3), Some(&30));
+    }
+
+    #[test]
+    fn test_replacing_resets_ttl() {
+        let mut cache: LruCache<u32, u32> = LruCache::new(2);
+        cache.insert_with_ttl(1, 10, Duration::ZERO);
+        // The old value expired, so nothing counts as replaced.
+        assert_eq!(cache.insert_with_ttl(1, 11, Duration::from_secs(3600)).replaced, None);
+        assert_eq!(cache.get(&1), Some(&11));
+
+        assert_eq!(cache.insert_with_ttl(1, 12, Duration::ZERO).replaced, Some(11));
+        assert_eq!(cache.remove(&1), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_default_ttl() {
+        let mut cache: LruCache<u32, u32> = LruCache::new(4);
+        cache.insert(1, 10);
+        cache.set_default_ttl(Some(Duration::ZERO));
+        assert_eq!(cache.default_ttl(), Some(Duration::ZERO));
+        cache.insert(2, 20);
+        cache.entry(3).or_insert(30);
+        cache.insert_with_ttl(4, 40, Duration::from_secs(3600));
+
+        assert!(matches!(cache.entry(2), Entry::Vacant(_)));
+        assert_eq!(cache.remove_expired(), [(3, 30)]);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&1), Some(&10));
+        assert_eq!(cache.get(&4), Some(&40));
+    }
+
+    #[test]
+    fn test_ttl_past_the_end_of_time_never_expires() {
+        let clock = MockClock::new();
+        let mut cache: LruCache<u32, u32> = LruCache::new(3);
+        cache.set_clock(clock.clone());
+        cache.insert_with_ttl(1, 10, Duration::MAX);
+        cache.set_default_ttl(Some(Duration::from_secs(u64::MAX)));
+        cache.insert(2, 20);
+        cache.entry(3).or_insert(30);
+
+        clock.advance(Duration::from_secs(3600 * 24 * 365 * 100));
+        assert!(cache.remove_expired().is_empty());
+        assert_eq!(cache.get(&1), Some(&10));
+        assert_eq!(cache.get(&2), Some(&20));
+        assert_eq!(cache.get(&3), Some(&30));
+    }
+
+    #[test]
+    fn test_expiry_follows_entries() {
+        let mut cache: LruCache<u32, u32> = LruCache::new(8);
+        for key in 0..6 {
+            cache.insert(key, key);
+        }
+        cache.insert_with_ttl(6, 6, Duration::ZERO);
+        cache.insert_with_ttl(7, 7, Duration::ZERO);
+
+        // A slot freed by an entry with a deadline is reused without one.
+        cache.remove(&6);
+        cache.insert(8, 8);
+        // Compacting moves the deadline of 7 along with it.
+        for key in 0..5 {
+            cache.remove(&key);
+        }
+        cache.shrink_to(0);
+
+        assert_eq!(cache.remove_expired(), [(7, 7)]);
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [8, 5]);
+    }
+
+    #[test]
+    fn test_time_to_idle_refreshes_on_use() {
+        let mut cache: LruCache<u32, u32> = LruCache::new(3);
+        cache.set_time_to_idle(Some(Duration::from_secs(3600)));
+        assert_eq!(cache.time_to_idle(), Some(Duration::from_secs(3600)));
+        cache.insert(1, 10);
+        cache.insert(2, 20);
+
+        // A use restarts the idle time with the current setting, which is
+        // now zero, so 1 goes idle right after this hit.
+        cache.set_time_to_idle(Some(Duration::ZERO));
+        assert_eq!(cache.get(&1), Some(&10));
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.get(&1), None);
+        // 2 was not used, so it keeps its old deadline.
+        assert_eq!(cache.peek(&2), Some(&20));
+
+        cache.set_time_to_idle(None);
+        assert_eq!(cache.get(&2), Some(&20));
+        cache.insert(3, 30);
+        assert_eq!(cache.get(&3), Some(&30));
+    }
+
+    #[test]
+    fn test_time_to_idle_past_the_end_of_time_never_expires() {
+        let clock = MockClock::new();
+        let mut cache: LruCache<u32, u32> = LruCache::new(3);
+        cache.set_clock(clock.clone());
+        cache.set_time_to_idle(Some(Duration::MAX));
+        cache.insert(1, 10);
+        cache.entry(2).or_insert(20);
+        assert_eq!(cache.get(&1), Some(&10));
+        *cache.get_mut(&2).unwrap() += 1;
+
+        clock.advance(Duration::from_secs(3600 * 24 * 365 * 100));
+        assert!(cache.remove_idle().is_empty());
+        assert_eq!(cache.get(&1), Some(&10));
+        assert_eq!(cache.get(&2), Some(&21));
+    }
+
+    #[test]
+    fn test_remove_idle_sweeps_from_tail() {
+        let mut cache: LruCache<u32, u32> = LruCache::new(4);
+        cache.set_time_to_idle(Some(Duration::ZERO));
+        cache.insert(1, 10);
+        cache.insert(2, 20);
+        cache.set_time_to_idle(Some(Duration::from_secs(3600)));
+        cache.insert(3, 30);
+        cache.insert(4, 40);
+
+        assert_eq!(cache.remove_idle(), [(1, 10), (2, 20)]);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.remove_idle().is_empty());
+        assert_eq!(cache.get(&3), Some(&30));
+        assert_eq!(cache.get(&4), Some(&40));
+    }
+
+    #[test]
+    fn test_ttl_with_mock_clock() {
+        let clock = MockClock::new();
+        let mut cache: LruCache<u32, u32> = LruCache::new(3);
+        cache.set_clock(clock.clone());
+        cache.set_default_ttl(Some(Duration::from_secs(10)));
+        cache.insert(1, 10);
+        cache.insert_with_ttl(2, 20, Duration::from_secs(30));
+
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(cache.get(&1), Some(&10));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.len(), 1);
+
+        // Replacing the value restarts its time to live.
+        clock.advance(Duration::from_secs(15));
+        cache.insert_with_ttl(2, 21, Duration::from_secs(30));
+        clock.advance(Duration::from_secs(29));
+        assert!(cache.remove_expired().is_empty());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.remove_expired(), [(2, 21)]);
+    }
+
+    #[test]
+    fn test_tti_with_mock_clock() {
+        let clock = MockClock::new();
+        let mut cache: LruCache<u32, u32> = LruCache::new(3);
+        cache.set_clock(clock.clone());
+        cache.set_time_to_idle(Some(Duration::from_secs(10)));
+        for key in 1..=3 {
+            cache.insert(key, key);
+            clock.advance(Duration::from_secs(1));
+        }
+
+        // Hits keep 1 alive, peeks do not keep 2 alive.
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(cache.get(&1), Some(&1));
+        assert_eq!(cache.peek(&2), Some(&2));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(cache.remove_idle(), [(2, 2)]);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.remove_idle(), [(3, 3)]);
+        assert_eq!(cache.get(&1), Some(&1));
+
+        // A time to live still applies to entries that keep being used.
+        cache.insert_with_ttl(4, 4, Duration::from_secs(15));
+        for _ in 0..2 {
+            clock.advance(Duration::from_secs(7));
+            assert_eq!(cache.get(&4), Some(&4));
+        }
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.get(&4), None);
+    }
 }
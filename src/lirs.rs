@@ -0,0 +1,434 @@
+//! Low Inter-reference Recency Set cache.
+
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+use crate::list::{Linked, Links, List};
+use crate::store::{Keyed, Store};
+use crate::{Evicted, InsertResult};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Status {
+    Lir,
+    // Resident HIR entries are on the queue.
+    Hir,
+    // Non-resident HIR entries are on the ghost list instead.
+    NonResident,
+}
+
+// Tags for the two lists a node can be on at once.
+struct Stack;
+struct Queue;
+
+struct LirsNode<K, V> {
+    // `None` once a HIR entry has been evicted but is still on the stack.
+    entry: Option<(K, V)>,
+    hash: u64,
+    status: Status,
+    in_stack: bool,
+    stack: Links,
+    // Links for the queue, or for the ghost list when non-resident.
+    queue: Links,
+}
+
+impl<K, V> Keyed for LirsNode<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    fn entry(&self) -> Option<(&K, &V)> {
+        self.entry.as_ref().map(|(key, value)| (key, value))
+    }
+
+    fn value_mut(&mut self) -> Option<&mut V> {
+        self.entry.as_mut().map(|(_, value)| value)
+    }
+
+    fn into_entry(self) -> Option<(K, V)> {
+        self.entry
+    }
+}
+
+impl<K, V> Linked<Stack> for LirsNode<K, V> {
+    fn links(&self) -> &Links {
+        &self.stack
+    }
+
+    fn links_mut(&mut self) -> &mut Links {
+        &mut self.stack
+    }
+}
+
+impl<K, V> Linked<Queue> for LirsNode<K, V> {
+    fn links(&self) -> &Links {
+        &self.queue
+    }
+
+    fn links_mut(&mut self) -> &mut Links {
+        &mut self.queue
+    }
+}
+
+/// A cache using the LIRS algorithm of Jiang and Zhang, which ranks entries
+/// by reuse distance rather than recency. It copes with loops larger than
+/// the cache, where [`LruCache`](crate::LruCache) misses every time.
+///
+/// Most of the capacity goes to LIR entries, those reused within a short
+/// distance. The rest holds HIR entries on the queue Q, which are evicted
+/// first. The stack S tracks recency for LIR entries, resident HIR entries
+/// and recently evicted HIR entries, and a HIR entry that is used again
+/// while still on the stack becomes LIR in place of the oldest LIR entry.
+///
+/// At most `capacity` evicted entries are remembered on the stack, by the
+/// hash of their key.
+pub struct LirsCache<K, V> {
+    capacity: usize,
+    lir_capacity: usize,
+    lir_len: usize,
+    store: Store<LirsNode<K, V>>,
+    // Most recent first.
+    stack: List<Stack>,
+    // Resident HIR entries, oldest first.
+    queue: List<Queue>,
+    // Non-resident HIR entries, oldest first.
+    ghosts: List<Queue>,
+}
+
+impl<K, V> LirsCache<K, V>
+    where K: Hash + Eq
+{
+    /// Creates a cache that gives 1% of its capacity to HIR entries.
+    pub fn new(capacity: usize) -> Self {
+        LirsCache::with_hir_ratio(capacity, 0.01)
+    }
+
+    /// Creates a cache that gives `hir_ratio` of its capacity, but at least
+    /// one entry, to HIR entries.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hir_ratio` is not between zero and one.
+    pub fn with_hir_ratio(capacity: usize, hir_ratio: f64) -> Self {
+        assert!((0.0..=1.0).contains(&hir_ratio), "hir_ratio must be between 0 and 1");
+        let hir_capacity = ((capacity as f64 * hir_ratio) as usize).clamp(1, capacity.max(1));
+        LirsCache {
+            capacity,
+            lir_capacity: capacity.saturating_sub(hir_capacity),
+            lir_len: 0,
+            store: Store::with_capacity(capacity),
+            stack: List::default(),
+            queue: List::default(),
+            ghosts: List::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.lir_len + self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if `key` is cached, without marking it as used.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.store.contains(key)
+    }
+
+    /// Looks up `key` without marking it as used.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.store.peek(key)
+    }
+
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.store.index_of(key)?;
+        self.access(index);
+        Some(self.store.value(index))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.store.index_of(key)?;
+        self.access(index);
+        Some(self.store.value_mut(index))
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> InsertResult<K, V> {
+        let hash = self.store.hash(&key);
+        let mut evicted = Evicted::new();
+
+        if let Some(index) = self.store.find(hash, &key) {
+            if let Some(old) = self.store.nodes[index].value_mut() {
+                let replaced = std::mem::replace(old, value);
+                self.access(index);
+                return InsertResult { replaced: Some(replaced), evicted };
+            }
+        }
+
+        if self.capacity == 0 {
+            evicted.push((key, value));
+            return InsertResult { replaced: None, evicted };
+        }
+
+        if self.len() >= self.capacity {
+            if self.queue.len() == 0 {
+                self.demote();
+            }
+            self.evict(&mut evicted);
+        }
+
+        // Evicting may have pruned the key's old node off the stack, so
+        // look it up again.
+        match self.store.find(hash, &key) {
+            Some(index) => {
+                // Reused while on the stack: its reuse distance is short.
+                self.ghosts.unlink(&mut self.store.nodes, index);
+                let node = &mut self.store.nodes[index];
+                node.entry = Some((key, value));
+                node.status = Status::Lir;
+                self.lir_len += 1;
+                self.stack.move_to_front(&mut self.store.nodes, index);
+                if self.lir_len > self.lir_capacity {
+                    self.demote();
+                }
+            }
+            None => {
+                let lir = self.lir_len < self.lir_capacity;
+                let index = self.store.insert(LirsNode {
+                    entry: Some((key, value)),
+                    hash,
+                    status: if lir { Status::Lir } else { Status::Hir },
+                    in_stack: true,
+                    stack: Links::default(),
+                    queue: Links::default(),
+                });
+                self.stack.push_front(&mut self.store.nodes, index);
+                if lir {
+                    self.lir_len += 1;
+                } else {
+                    self.queue.push_back(&mut self.store.nodes, index);
+                }
+            }
+        }
+        self.prune();
+        InsertResult { replaced: None, evicted }
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        let index = self.store.index_of(key)?;
+        match self.store.nodes[index].status {
+            Status::Lir => self.lir_len -= 1,
+            _ => self.queue.unlink(&mut self.store.nodes, index),
+        }
+        let entry = self.remove_node(index);
+        self.prune();
+        entry
+    }
+}
+
+impl<K, V> LirsCache<K, V> {
+    /// Records a hit on a resident entry.
+    fn access(&mut self, index: u32) {
+        let node = &self.store.nodes[index];
+        match (node.status, node.in_stack) {
+            (Status::Lir, _) => {
+                self.stack.move_to_front(&mut self.store.nodes, index);
+                self.prune();
+            }
+            (_, true) => {
+                self.queue.unlink(&mut self.store.nodes, index);
+                self.store.nodes[index].status = Status::Lir;
+                self.lir_len += 1;
+                self.stack.move_to_front(&mut self.store.nodes, index);
+                if self.lir_len > self.lir_capacity {
+                    self.demote();
+                }
+            }
+            (_, false) => {
+                self.store.nodes[index].in_stack = true;
+                self.stack.push_front(&mut self.store.nodes, index);
+                self.queue.move_to_back(&mut self.store.nodes, index);
+            }
+        }
+    }
+
+    /// Turns the LIR entry at the bottom of the stack into a resident HIR
+    /// entry at the end of the queue.
+    fn demote(&mut self) {
+        let Some(index) = self.stack.pop_back(&mut self.store.nodes) else {
+            return;
+        };
+        let node = &mut self.store.nodes[index];
+        node.status = Status::Hir;
+        node.in_stack = false;
+        self.lir_len -= 1;
+        self.queue.push_back(&mut self.store.nodes, index);
+        self.prune();
+    }
+
+    /// Evicts the resident HIR entry at the front of the queue. If it is
+    /// still on the stack it stays there as a non-resident entry.
+    fn evict(&mut self, evicted: &mut Evicted<K, V>) {
+        let Some(index) = self.queue.pop_front(&mut self.store.nodes) else {
+            return;
+        };
+        if !self.store.nodes[index].in_stack {
+            evicted.push(self.remove_node(index).unwrap());
+            return;
+        }
+
+        let node = &mut self.store.nodes[index];
+        node.status = Status::NonResident;
+        evicted.push(node.entry.take().unwrap());
+        self.ghosts.push_back(&mut self.store.nodes, index);
+        if self.ghosts.len() > self.capacity {
+            let oldest = self.ghosts.pop_front(&mut self.store.nodes).unwrap();
+            self.remove_node(oldest);
+        }
+    }
+
+    /// Removes HIR entries from the bottom of the stack, so that it ends
+    /// with the oldest LIR entry. Non-resident ones are forgotten.
+    fn prune(&mut self) {
+        while let Some(index) = self.stack.tail() {
+            match self.store.nodes[index].status {
+                Status::Lir => break,
+                Status::Hir => {
+                    self.stack.unlink(&mut self.store.nodes, index);
+                    self.store.nodes[index].in_stack = false;
+                }
+                Status::NonResident => {
+                    self.ghosts.unlink(&mut self.store.nodes, index);
+                    self.remove_node(index);
+                }
+            }
+        }
+    }
+
+    /// Drops a node from the stack and the store. The caller
+    /// takes care of the queue or ghost list and the LIR count.
+    fn remove_node(&mut self, index: u32) -> Option<(K, V)> {
+        if self.store.nodes[index].in_stack {
+            self.stack.unlink(&mut self.store.nodes, index);
+        }
+        self.store.remove(index).into_entry()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    fn evicted<K, V>(result: InsertResult<K, V>) -> Vec<(K, V)> {
+        result.evicted.into_iter().collect()
+    }
+
+    fn status(cache: &LirsCache<u32, u32>, key: u32) -> Status {
+        let index = cache.store.find(cache.store.hash(&key), &key).unwrap();
+        cache.store.nodes[index].status
+    }
+
+    #[test]
+    fn test_lookups_skip_non_resident_entries() {
+        let mut cache = LirsCache::with_hir_ratio(3, 0.34);
+        for key in 1..=3 {
+            cache.insert(key, key);
+        }
+        *cache.get_mut(&2).unwrap() += 10;
+        assert_eq!(evicted(cache.insert(4, 4)), [(3, 3)]);
+
+        // 3 is still on the stack, but only as a hash and a status.
+        assert_eq!(status(&cache, 3), Status::NonResident);
+        assert!(!cache.contains(&3));
+        assert_eq!(cache.peek(&3), None);
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.remove(&3), None);
+        assert_eq!(status(&cache, 3), Status::NonResident);
+        assert_eq!(cache.len(), 3);
+
+        assert_eq!(cache.peek(&2), Some(&12));
+        assert_eq!(cache.remove_entry(&2), Some((2, 12)));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_zero_capacity_hands_entry_back() {
+        let mut cache: LirsCache<u32, u32> = LirsCache::new(0);
+        assert_eq!(evicted(cache.insert(1, 1)), [(1, 1)]);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_hir_reuse_on_stack_becomes_lir() {
+        let mut cache = LirsCache::with_hir_ratio(3, 0.34);
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        cache.insert(3, 3);
+        assert_eq!(status(&cache, 2), Status::Lir);
+        assert_eq!(status(&cache, 3), Status::Hir);
+
+        // 3 is evicted but remembered; coming back it replaces 1, the
+        // oldest LIR entry.
+        assert_eq!(evicted(cache.insert(4, 4)), [(3, 3)]);
+        assert_eq!(status(&cache, 3), Status::NonResident);
+        assert_eq!(evicted(cache.insert(3, 3)), [(4, 4)]);
+        assert_eq!(status(&cache, 3), Status::Lir);
+        assert_eq!(status(&cache, 1), Status::Hir);
+        assert_eq!(cache.len(), 3);
+
+        // A hit on 1 while it is off the stack keeps it HIR.
+        cache.get(&1);
+        assert_eq!(status(&cache, 1), Status::Hir);
+        cache.get(&1);
+        assert_eq!(status(&cache, 1), Status::Lir);
+        assert_eq!(status(&cache, 2), Status::Hir);
+    }
+
+    #[test]
+    fn test_loop_larger_than_cache() {
+        let mut lirs = LirsCache::new(100);
+        let mut lru = LruCache::new(100);
+        let (mut lirs_hits, mut lru_hits) = (0, 0);
+        for _ in 0..20 {
+            for key in 0..150u32 {
+                match lirs.get(&key) {
+                    Some(_) => lirs_hits += 1,
+                    None => { lirs.insert(key, ()); }
+                }
+                match lru.get(&key) {
+                    Some(_) => lru_hits += 1,
+                    None => { lru.insert(key, ()); }
+                }
+            }
+        }
+
+        assert_eq!(lru_hits, 0);
+        // All but the first round should hit on the 99 LIR entries.
+        assert!(lirs_hits >= 19 * 95, "lirs: {lirs_hits}");
+        assert_eq!(lirs.len(), 100);
+    }
+}
@@ -0,0 +1,166 @@
+//! Intrusive doubly linked lists threaded through a [`Slab`].
+
+use std::marker::PhantomData;
+
+use crate::slab::Slab;
+
+/// Index used as a null link.
+pub(crate) const NIL: u32 = u32::MAX;
+
+#[derive(Clone, Copy)]
+pub(crate) struct Links {
+    pub(crate) prev: u32,
+    pub(crate) next: u32,
+}
+
+impl Default for Links {
+    fn default() -> Self {
+        Links { prev: NIL, next: NIL }
+    }
+}
+
+/// A slab node that carries its own list links. Nodes that sit on more than
+/// one list at a time implement this once per list, each with its own `Tag`.
+pub(crate) trait Linked<Tag = ()> {
+    fn links(&self) -> &Links;
+    fn links_mut(&mut self) -> &mut Links;
+}
+
+/// Head and tail of one list. The nodes themselves live in a slab, so every
+/// operation takes the slab the list was built over.
+pub(crate) struct List<Tag = ()> {
+    head: u32,
+    tail: u32,
+    len: usize,
+    tag: PhantomData<fn() -> Tag>,
+}
+
+impl<Tag> Default for List<Tag> {
+    fn default() -> Self {
+        List { head: NIL, tail: NIL, len: 0, tag: PhantomData }
+    }
+}
+
+impl<Tag> List<Tag> {
+    fn links<T: Linked<Tag>>(slab: &Slab<T>, index: u32) -> &Links {
+        Linked::<Tag>::links(&slab[index])
+    }
+
+    fn links_mut<T: Linked<Tag>>(slab: &mut Slab<T>, index: u32) -> &mut Links {
+        Linked::<Tag>::links_mut(&mut slab[index])
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn head(&self) -> Option<u32> {
+        (self.head != NIL).then_some(self.head)
+    }
+
+    pub(crate) fn tail(&self) -> Option<u32> {
+        (self.tail != NIL).then_some(self.tail)
+    }
+
+    pub(crate) fn next<T: Linked<Tag>>(&self, slab: &Slab<T>, index: u32) -> Option<u32> {
+        let next = Self::links(slab, index).next;
+        (next != NIL).then_some(next)
+    }
+
+    pub(crate) fn prev<T: Linked<Tag>>(&self, slab: &Slab<T>, index: u32) -> Option<u32> {
+        let prev = Self::links(slab, index).prev;
+        (prev != NIL).then_some(prev)
+    }
+
+    pub(crate) fn unlink<T: Linked<Tag>>(&mut self, slab: &mut Slab<T>, index: u32) {
+        let Links { prev, next } = *Self::links(slab, index);
+
+        if prev != NIL {
+            Self::links_mut(slab, prev).next = next;
+        } else {
+            self.head = next;
+        }
+
+        if next != NIL {
+            Self::links_mut(slab, next).prev = prev;
+        } else {
+            self.tail = prev;
+        }
+
+        *Self::links_mut(slab, index) = Links::default();
+        self.len -= 1;
+    }
+
+    pub(crate) fn push_front<T: Linked<Tag>>(&mut self, slab: &mut Slab<T>, index: u32) {
+        *Self::links_mut(slab, index) = Links { prev: NIL, next: self.head };
+
+        if self.head != NIL {
+            Self::links_mut(slab, self.head).prev = index;
+        } else {
+            self.tail = index;
+        }
+
+        self.head = index;
+        self.len += 1;
+    }
+
+    pub(crate) fn push_back<T: Linked<Tag>>(&mut self, slab: &mut Slab<T>, index: u32) {
+        *Self::links_mut(slab, index) = Links { prev: self.tail, next: NIL };
+
+        if self.tail != NIL {
+            Self::links_mut(slab, self.tail).next = index;
+        } else {
+            self.head = index;
+        }
+
+        self.tail = index;
+        self.len += 1;
+    }
+
+    /// Links `index` just before `anchor`, which must be on this list.
+    pub(crate) fn insert_before<T: Linked<Tag>>(&mut self, slab: &mut Slab<T>, index: u32, anchor: u32) {
+        let prev = Self::links(slab, anchor).prev;
+        *Self::links_mut(slab, index) = Links { prev, next: anchor };
+        Self::links_mut(slab, anchor).prev = index;
+
+        if prev != NIL {
+            Self::links_mut(slab, prev).next = index;
+        } else {
+            self.head = index;
+        }
+        self.len += 1;
+    }
+
+    pub(crate) fn pop_front<T: Linked<Tag>>(&mut self, slab: &mut Slab<T>) -> Option<u32> {
+        let index = self.head()?;
+        self.unlink(slab, index);
+        Some(index)
+    }
+
+    pub(crate) fn pop_back<T: Linked<Tag>>(&mut self, slab: &mut Slab<T>) -> Option<u32> {
+        let index = self.tail()?;
+        self.unlink(slab, index);
+        Some(index)
+    }
+
+    pub(crate) fn move_to_front<T: Linked<Tag>>(&mut self, slab: &mut Slab<T>, index: u32) {
+        if self.head != index {
+            self.unlink(slab, index);
+            self.push_front(slab, index);
+        }
+    }
+
+    pub(crate) fn move_to_back<T: Linked<Tag>>(&mut self, slab: &mut Slab<T>, index: u32) {
+        if self.tail != index {
+            self.unlink(slab, index);
+            self.push_back(slab, index);
+        }
+    }
+
+    pub(crate) fn move_before<T: Linked<Tag>>(&mut self, slab: &mut Slab<T>, index: u32, anchor: u32) {
+        if index != anchor && Self::links(slab, anchor).prev != index {
+            self.unlink(slab, index);
+            self.insert_before(slab, index, anchor);
+        }
+    }
+}
@@ -0,0 +1,1451 @@
+//! Eviction policies for [`Cache`](crate::Cache).
+//!
+//! A cache keeps all of its entries on one list. New entries are linked at
+//! the front, and the policy is told about every insertion, hit and removal.
+//! It may reorder entries and tag them with a few bits of its own, and it
+//! picks the victim whenever the cache needs room. Policies never add or
+//! drop entries themselves, so the list always holds exactly the cached
+//! entries; the cache's iterators walk it from front to back.
+
+use crate::ghost::GhostList;
+use crate::list::List;
+use crate::sketch::FrequencySketch;
+use crate::slab::Slab;
+use crate::Node;
+
+/// Handle to a cached entry, valid until that entry is removed or relocated
+/// (see [`EvictionPolicy::on_relocate`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Slot(pub(crate) u32);
+
+impl Slot {
+    /// A dense index that can be used to keep per-entry state on the side.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// The ordered entry list of a cache, as seen by its [`EvictionPolicy`].
+pub struct Order<'a, K, V> {
+    entries: &'a mut Slab<Node<K, V>>,
+    list: &'a mut List,
+}
+
+impl<'a, K, V> Order<'a, K, V> {
+    pub(crate) fn new(entries: &'a mut Slab<Node<K, V>>, list: &'a mut List) -> Self {
+        Order { entries, list }
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.len() == 0
+    }
+
+    pub fn front(&self) -> Option<Slot> {
+        self.list.head().map(Slot)
+    }
+
+    pub fn back(&self) -> Option<Slot> {
+        self.list.tail().map(Slot)
+    }
+
+    pub fn next(&self, slot: Slot) -> Option<Slot> {
+        self.list.next(self.entries, slot.0).map(Slot)
+    }
+
+    pub fn prev(&self, slot: Slot) -> Option<Slot> {
+        self.list.prev(self.entries, slot.0).map(Slot)
+    }
+
+    pub fn move_to_front(&mut self, slot: Slot) {
+        self.list.move_to_front(self.entries, slot.0);
+    }
+
+    pub fn move_to_back(&mut self, slot: Slot) {
+        self.list.move_to_back(self.entries, slot.0);
+    }
+
+    /// Moves `slot` so that it sits just in front of `anchor`.
+    pub fn move_before(&mut self, slot: Slot, anchor: Slot) {
+        self.list.move_before(self.entries, slot.0, anchor.0);
+    }
+
+    /// Policy-defined bits stored with the entry. New entries start at zero.
+    pub fn flags(&self, slot: Slot) -> u8 {
+        self.entries[slot.0].flags
+    }
+
+    pub fn set_flags(&mut self, slot: Slot, flags: u8) {
+        self.entries[slot.0].flags = flags;
+    }
+
+    /// The hash of the entry's key, for policies that remember keys after
+    /// they have been evicted.
+    pub fn hash(&self, slot: Slot) -> u64 {
+        self.entries[slot.0].hash
+    }
+}
+
+/// Decides which entry a cache evicts next.
+pub trait EvictionPolicy {
+    /// Called after a new entry has been linked at the front.
+    fn on_insert<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        let _ = (order, slot);
+    }
+
+    /// Called when an entry is read or replaced through a method that counts
+    /// as a use, such as `get` or `insert`. Peeking does not call this.
+    fn on_access<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        let _ = (order, slot);
+    }
+
+    /// Called just before an entry is unlinked, whether it was evicted or
+    /// removed explicitly.
+    fn on_remove<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        let _ = (order, slot);
+    }
+
+    /// Picks the next entry to evict. Only called on a non-empty cache.
+    fn victim<K, V>(&mut self, order: &mut Order<'_, K, V>) -> Slot;
+
+    /// Picks the next entry to evict other than `keep`, an entry that was
+    /// just replaced or grew and must stay. Only called when the cache holds
+    /// another entry. By default this asks [`victim`](Self::victim) and, if
+    /// that picks `keep`, takes the entry next to it instead.
+    fn victim_except<K, V>(&mut self, order: &mut Order<'_, K, V>, keep: Slot) -> Slot {
+        let slot = self.victim(order);
+        if slot == keep { next_to(order, keep) } else { slot }
+    }
+
+    /// Called after `resize` or `shrink_to` moved the entries to new slots.
+    /// `relocated` maps an entry's old slot to its new one. The order and
+    /// flags of the entries are unchanged.
+    fn on_relocate<F: Fn(Slot) -> Slot>(&mut self, relocated: F) {
+        let _ = relocated;
+    }
+
+    /// Called when every entry is removed at once by `drain`.
+    fn on_clear(&mut self) {}
+
+    /// Called with the cache's capacity when the cache is created, and again
+    /// whenever `resize` changes it. For a weighted cache this is the
+    /// maximum total weight.
+    fn on_resize(&mut self, capacity: usize) {
+        let _ = capacity;
+    }
+}
+
+/// The entry in front of `slot`, or behind it if `slot` is at the front.
+fn next_to<K, V>(order: &Order<'_, K, V>, slot: Slot) -> Slot {
+    order.prev(slot).or_else(|| order.next(slot)).unwrap()
+}
+
+/// The part of `len` entries that `ratio` stands for, rounded down.
+fn share(ratio: f64, len: usize) -> usize {
+    (len as f64 * ratio) as usize
+}
+
+/// Least recently used: hits move an entry to the front, and the back is
+/// evicted.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lru;
+
+impl EvictionPolicy for Lru {
+    fn on_access<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        order.move_to_front(slot);
+    }
+
+    fn victim<K, V>(&mut self, order: &mut Order<'_, K, V>) -> Slot {
+        order.back().unwrap()
+    }
+}
+
+/// First in, first out: hits change nothing, and the oldest entry is
+/// evicted.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fifo;
+
+impl EvictionPolicy for Fifo {
+    fn victim<K, V>(&mut self, order: &mut Order<'_, K, V>) -> Slot {
+        order.back().unwrap()
+    }
+}
+
+/// Most recently used: hits move an entry to the front, and the front is
+/// evicted. Useful for cyclic scans larger than the cache.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Mru;
+
+impl EvictionPolicy for Mru {
+    fn on_access<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        order.move_to_front(slot);
+    }
+
+    fn victim<K, V>(&mut self, order: &mut Order<'_, K, V>) -> Slot {
+        order.front().unwrap()
+    }
+}
+
+/// CLOCK, also known as second chance: a hit only sets a reference bit.
+/// When looking for a victim, referenced entries at the back get their bit
+/// cleared and go round again instead of being evicted.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Clock;
+
+const REFERENCED: u8 = 1;
+
+impl EvictionPolicy for Clock {
+    fn on_access<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        order.set_flags(slot, REFERENCED);
+    }
+
+    fn victim<K, V>(&mut self, order: &mut Order<'_, K, V>) -> Slot {
+        loop {
+            let slot = order.back().unwrap();
+            if order.flags(slot) & REFERENCED == 0 {
+                return slot;
+            }
+            order.set_flags(slot, 0);
+            order.move_to_front(slot);
+        }
+    }
+}
+
+/// Segmented LRU: new entries start in a probationary segment, and a second
+/// hit promotes them to a protected segment of limited size. When the
+/// protected segment overflows, its least recently used entry is demoted
+/// back to the front of the probationary segment rather than evicted.
+/// Victims come from the probationary segment first, so a scan of one-hit
+/// keys cannot flush the protected entries.
+///
+/// Both segments share the cache's list: protected entries come first,
+/// followed by the probationary ones.
+#[derive(Clone, Debug)]
+pub struct Slru {
+    protected_ratio: f64,
+    // The most entries the protected segment holds, from the capacity.
+    protected_capacity: usize,
+    protected_len: usize,
+    // First entry of the probationary segment.
+    probation: Option<Slot>,
+}
+
+const PROTECTED: u8 = 1;
+
+impl Slru {
+    /// Creates a policy whose protected segment takes up at most
+    /// `protected_ratio` of the cache's capacity, and at least one entry
+    /// unless the ratio is zero. The default is 0.8.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `protected_ratio` is not between zero and one.
+    pub fn new(protected_ratio: f64) -> Self {
+        assert!((0.0..=1.0).contains(&protected_ratio), "protected_ratio must be between 0 and 1");
+        Slru {
+            protected_ratio,
+            // Set by `on_resize` once the cache's capacity is known.
+            protected_capacity: 0,
+            protected_len: 0,
+            probation: None,
+        }
+    }
+
+    pub fn protected_ratio(&self) -> f64 {
+        self.protected_ratio
+    }
+
+    /// The number of entries in the protected segment.
+    pub fn protected_len(&self) -> usize {
+        self.protected_len
+    }
+
+    fn is_protected<K, V>(order: &Order<'_, K, V>, slot: Slot) -> bool {
+        order.flags(slot) & PROTECTED != 0
+    }
+}
+
+impl Default for Slru {
+    fn default() -> Self {
+        Slru::new(0.8)
+    }
+}
+
+impl EvictionPolicy for Slru {
+    fn on_insert<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        match self.probation {
+            Some(probation) => order.move_before(slot, probation),
+            None => order.move_to_back(slot),
+        }
+        self.probation = Some(slot);
+    }
+
+    fn on_access<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        if !Self::is_protected(order, slot) {
+            if self.probation == Some(slot) {
+                self.probation = order.next(slot);
+            }
+            order.set_flags(slot, PROTECTED);
+            self.protected_len += 1;
+        }
+        order.move_to_front(slot);
+
+        // The limit shrinks along with the cache, so more than one entry
+        // may need demoting after a resize.
+        while self.protected_len > self.protected_capacity {
+            // The protected tail sits right in front of the probationary
+            // segment, so demoting it only moves the boundary.
+            let demoted = match self.probation {
+                Some(probation) => order.prev(probation).unwrap(),
+                None => order.back().unwrap(),
+            };
+            order.set_flags(demoted, 0);
+            self.protected_len -= 1;
+            self.probation = Some(demoted);
+        }
+    }
+
+    fn on_remove<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        if Self::is_protected(order, slot) {
+            self.protected_len -= 1;
+        } else if self.probation == Some(slot) {
+            self.probation = order.next(slot);
+        }
+    }
+
+    fn victim<K, V>(&mut self, order: &mut Order<'_, K, V>) -> Slot {
+        // The back is the probationary tail, or the protected tail once the
+        // probationary segment is empty.
+        order.back().unwrap()
+    }
+
+    fn on_relocate<F: Fn(Slot) -> Slot>(&mut self, relocated: F) {
+        self.probation = self.probation.map(relocated);
+    }
+
+    fn on_clear(&mut self) {
+        self.protected_len = 0;
+        self.probation = None;
+    }
+
+    fn on_resize(&mut self, capacity: usize) {
+        self.protected_capacity = match share(self.protected_ratio, capacity) {
+            0 if self.protected_ratio > 0.0 => 1,
+            limit => limit,
+        };
+    }
+}
+
+/// The 2Q algorithm of Johnson and Shasha. New entries go to A1in, a FIFO
+/// whose hits change nothing. Entries evicted from A1in are remembered in
+/// A1out, a ghost queue of key hashes, and a key that is inserted again
+/// while in A1out goes straight to Am, an LRU of the entries seen more than
+/// once. Victims come from A1in while it is over its share of the cache,
+/// and from Am otherwise.
+///
+/// Both queues share the cache's list: Am comes first, followed by A1in.
+#[derive(Debug)]
+pub struct TwoQueue {
+    in_ratio: f64,
+    out_ratio: f64,
+    in_len: usize,
+    // First entry of A1in.
+    in_head: Option<Slot>,
+    // The slot `victim` last picked. An A1in entry only goes to A1out when
+    // it is evicted, not when the cache's user removes it.
+    evicting: Option<Slot>,
+    out: GhostList,
+}
+
+const HOT: u8 = 1;
+
+impl TwoQueue {
+    /// Creates a policy that lets A1in take up `in_ratio` of the cached
+    /// entries and remembers up to `out_ratio` times as many evicted keys in
+    /// A1out. The paper suggests 0.25 and 0.5.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either ratio is negative, or `in_ratio` is above one.
+    pub fn new(in_ratio: f64, out_ratio: f64) -> Self {
+        assert!((0.0..=1.0).contains(&in_ratio), "in_ratio must be between 0 and 1");
+        assert!(out_ratio >= 0.0, "out_ratio must not be negative");
+        TwoQueue {
+            in_ratio,
+            out_ratio,
+            in_len: 0,
+            in_head: None,
+            evicting: None,
+            out: GhostList::with_capacity(0),
+        }
+    }
+
+    /// The number of entries in A1in.
+    pub fn in_len(&self) -> usize {
+        self.in_len
+    }
+
+    /// The number of evicted keys remembered in A1out.
+    pub fn out_len(&self) -> usize {
+        self.out.len()
+    }
+}
+
+impl Default for TwoQueue {
+    fn default() -> Self {
+        TwoQueue::new(0.25, 0.5)
+    }
+}
+
+impl EvictionPolicy for TwoQueue {
+    fn on_insert<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        if self.out.remove(order.hash(slot)) {
+            order.set_flags(slot, HOT);
+            return;
+        }
+
+        match self.in_head {
+            Some(head) => order.move_before(slot, head),
+            None => order.move_to_back(slot),
+        }
+        self.in_head = Some(slot);
+        self.in_len += 1;
+    }
+
+    fn on_access<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        if order.flags(slot) & HOT != 0 {
+            order.move_to_front(slot);
+        }
+    }
+
+    fn on_remove<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        let evicting = self.evicting.take() == Some(slot);
+        if order.flags(slot) & HOT != 0 {
+            return;
+        }
+
+        if self.in_head == Some(slot) {
+            self.in_head = order.next(slot);
+        }
+        self.in_len -= 1;
+        if evicting {
+            self.out.push_front(order.hash(slot));
+            let limit = share(self.out_ratio, order.len());
+            while self.out.len() > limit {
+                self.out.pop_back();
+            }
+        }
+    }
+
+    fn victim<K, V>(&mut self, order: &mut Order<'_, K, V>) -> Slot {
+        let in_full = self.in_len > share(self.in_ratio, order.len());
+        let slot = match self.in_head {
+            // Take the Am tail, which sits right in front of A1in.
+            Some(head) if !in_full => order.prev(head).unwrap_or_else(|| order.back().unwrap()),
+            _ => order.back().unwrap(),
+        };
+        self.evicting = Some(slot);
+        slot
+    }
+
+    fn victim_except<K, V>(&mut self, order: &mut Order<'_, K, V>, keep: Slot) -> Slot {
+        let mut slot = self.victim(order);
+        if slot == keep {
+            slot = next_to(order, keep);
+            self.evicting = Some(slot);
+        }
+        slot
+    }
+
+    fn on_relocate<F: Fn(Slot) -> Slot>(&mut self, relocated: F) {
+        self.in_head = self.in_head.map(relocated);
+    }
+
+    fn on_clear(&mut self) {
+        self.in_len = 0;
+        self.in_head = None;
+        self.evicting = None;
+        self.out.clear();
+    }
+}
+
+/// SIEVE: a FIFO queue where a hit only sets a visited bit. A hand walks
+/// from the oldest entry towards the newest, clearing visited bits, and
+/// evicts the first entry it finds unvisited. The hand stays where it
+/// stopped, so entries that survive keep their place instead of being moved
+/// to the front as in [`Clock`].
+#[derive(Clone, Debug, Default)]
+pub struct Sieve {
+    hand: Option<Slot>,
+}
+
+const VISITED: u8 = 1;
+
+impl EvictionPolicy for Sieve {
+    fn on_access<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        order.set_flags(slot, VISITED);
+    }
+
+    fn on_remove<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        if self.hand == Some(slot) {
+            self.hand = order.prev(slot);
+        }
+    }
+
+    fn victim<K, V>(&mut self, order: &mut Order<'_, K, V>) -> Slot {
+        let mut slot = self.hand.unwrap_or_else(|| order.back().unwrap());
+        while order.flags(slot) & VISITED != 0 {
+            order.set_flags(slot, 0);
+            slot = order.prev(slot).unwrap_or_else(|| order.back().unwrap());
+        }
+        self.hand = Some(slot);
+        slot
+    }
+
+    fn on_relocate<F: Fn(Slot) -> Slot>(&mut self, relocated: F) {
+        self.hand = self.hand.map(relocated);
+    }
+
+    fn on_clear(&mut self) {
+        self.hand = None;
+    }
+}
+
+/// W-TinyLFU: a small LRU window in front of a main segmented LRU, with
+/// admission to the main region filtered by access frequency.
+///
+/// New entries go to the window, and entries pushed out of the window move
+/// to the front of the main region's probationary segment. Once the cache
+/// is full, the window's tail is the candidate for that move: it competes
+/// with the probationary tail, and whichever has the lower estimated
+/// frequency is evicted, the candidate losing ties. Frequencies come from a
+/// Count-Min Sketch of recent
+/// accesses that is halved periodically, so popularity fades over time. The
+/// main region works like [`Slru`].
+///
+/// The segments share the cache's list: the window comes first, followed by
+/// the protected and then the probationary segment.
+#[derive(Debug)]
+pub struct TinyLfu {
+    window_ratio: f64,
+    protected_ratio: f64,
+    window_len: usize,
+    protected_len: usize,
+    probation_len: usize,
+    // First entries of the protected and probationary segments.
+    protected: Option<Slot>,
+    probation: Option<Slot>,
+    sketch: FrequencySketch,
+}
+
+const WINDOW: u8 = 0;
+const MAIN_PROBATION: u8 = 1;
+const MAIN_PROTECTED: u8 = 2;
+
+// Small caches still get a sketch this large, or it would age after a
+// handful of accesses.
+const MIN_SKETCH_CAPACITY: usize = 64;
+
+impl TinyLfu {
+    /// Creates a policy whose window takes up `window_ratio` of the cached
+    /// entries, and whose protected segment takes up `protected_ratio` of the
+    /// rest. The window always holds at least one entry. Caffeine uses 0.01
+    /// and 0.8.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either ratio is not between zero and one.
+    pub fn new(window_ratio: f64, protected_ratio: f64) -> Self {
+        assert!((0.0..=1.0).contains(&window_ratio), "window_ratio must be between 0 and 1");
+        assert!((0.0..=1.0).contains(&protected_ratio), "protected_ratio must be between 0 and 1");
+        TinyLfu {
+            window_ratio,
+            protected_ratio,
+            window_len: 0,
+            protected_len: 0,
+            probation_len: 0,
+            protected: None,
+            probation: None,
+            // Sized by `on_resize` once the cache's capacity is known.
+            sketch: FrequencySketch::with_capacity(MIN_SKETCH_CAPACITY),
+        }
+    }
+
+    /// The number of entries in the window.
+    pub fn window_len(&self) -> usize {
+        self.window_len
+    }
+
+    /// The number of entries in the protected segment.
+    pub fn protected_len(&self) -> usize {
+        self.protected_len
+    }
+
+    /// Forgets `slot` as the head of its segment, before it is moved or
+    /// removed.
+    fn detach<K, V>(&mut self, order: &Order<'_, K, V>, slot: Slot) {
+        let flags = order.flags(slot);
+        let next = order.next(slot).filter(|&next| order.flags(next) == flags);
+        if self.protected == Some(slot) {
+            self.protected = next;
+        } else if self.probation == Some(slot) {
+            self.probation = next;
+        }
+    }
+
+    fn push_protected<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        match self.protected.or(self.probation) {
+            Some(head) => order.move_before(slot, head),
+            None => order.move_to_back(slot),
+        }
+        order.set_flags(slot, MAIN_PROTECTED);
+        self.protected = Some(slot);
+        self.protected_len += 1;
+    }
+
+    fn push_probation<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        match self.probation {
+            Some(head) => order.move_before(slot, head),
+            None => order.move_to_back(slot),
+        }
+        order.set_flags(slot, MAIN_PROBATION);
+        self.probation = Some(slot);
+        self.probation_len += 1;
+    }
+
+    /// The last entry in front of the segment starting at `next_head`.
+    fn tail_before<K, V>(order: &Order<'_, K, V>, next_head: Option<Slot>) -> Option<Slot> {
+        match next_head {
+            Some(head) => order.prev(head),
+            None => order.back(),
+        }
+    }
+}
+
+impl Default for TinyLfu {
+    fn default() -> Self {
+        TinyLfu::new(0.01, 0.8)
+    }
+}
+
+impl EvictionPolicy for TinyLfu {
+    fn on_insert<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        self.sketch.increment(order.hash(slot));
+        self.window_len += 1;
+
+        let window_capacity = share(self.window_ratio, order.len()).max(1);
+        while self.window_len > window_capacity {
+            let tail = Self::tail_before(order, self.protected.or(self.probation)).unwrap();
+            self.window_len -= 1;
+            self.push_probation(order, tail);
+        }
+    }
+
+    fn on_access<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        self.sketch.increment(order.hash(slot));
+        match order.flags(slot) {
+            WINDOW => order.move_to_front(slot),
+            MAIN_PROTECTED => {
+                self.detach(order, slot);
+                self.protected_len -= 1;
+                self.push_protected(order, slot);
+            }
+            _ => {
+                self.detach(order, slot);
+                self.probation_len -= 1;
+                self.push_protected(order, slot);
+
+                let main_len = self.protected_len + self.probation_len;
+                if self.protected_len > share(self.protected_ratio, main_len) {
+                    let demoted = Self::tail_before(order, self.probation).unwrap();
+                    self.detach(order, demoted);
+                    self.protected_len -= 1;
+                    self.push_probation(order, demoted);
+                }
+            }
+        }
+    }
+
+    fn on_remove<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        self.detach(order, slot);
+        match order.flags(slot) {
+            WINDOW => self.window_len -= 1,
+            MAIN_PROTECTED => self.protected_len -= 1,
+            _ => self.probation_len -= 1,
+        }
+    }
+
+    fn victim<K, V>(&mut self, order: &mut Order<'_, K, V>) -> Slot {
+        // The back is the probationary tail, or the tail of the protected
+        // segment or the window once the segments in front are empty.
+        let victim = order.back().unwrap();
+        // Only a full window has an entry to hand to the main region.
+        if self.window_len < share(self.window_ratio, order.len()).max(1) {
+            return victim;
+        }
+        match Self::tail_before(order, self.protected.or(self.probation)) {
+            Some(candidate) if candidate != victim => {
+                let candidate_frequency = self.sketch.frequency(order.hash(candidate));
+                if candidate_frequency > self.sketch.frequency(order.hash(victim)) {
+                    victim
+                } else {
+                    candidate
+                }
+            }
+            _ => victim,
+        }
+    }
+
+    fn on_relocate<F: Fn(Slot) -> Slot>(&mut self, relocated: F) {
+        self.protected = self.protected.map(&relocated);
+        self.probation = self.probation.map(relocated);
+    }
+
+    fn on_clear(&mut self) {
+        self.window_len = 0;
+        self.protected_len = 0;
+        self.probation_len = 0;
+        self.protected = None;
+        self.probation = None;
+    }
+
+    fn on_resize(&mut self, capacity: usize) {
+        self.sketch.resize(capacity.max(MIN_SKETCH_CAPACITY));
+    }
+}
+
+/// S3-FIFO, after Yang et al.: three FIFO queues instead of any list
+/// reordering on hits. New entries go to a small queue, and a hit only bumps
+/// a two-bit frequency counter. Entries leaving the small queue move on to
+/// the main queue if they were hit there, and are otherwise evicted with
+/// their key remembered in a ghost queue. A key that comes back while in
+/// the ghost queue goes straight to the main queue. The main queue evicts
+/// its oldest entry once that entry's counter has run down, reinserting it
+/// and decrementing the counter until then.
+///
+/// The small and main queues share the cache's list: the small queue comes
+/// first, followed by the main queue.
+#[derive(Debug)]
+pub struct S3Fifo {
+    small_ratio: f64,
+    ghost_ratio: f64,
+    small_len: usize,
+    main_len: usize,
+    // First entry of the main queue.
+    main: Option<Slot>,
+    // The slot `victim` last picked. A small-queue entry only becomes a
+    // ghost when it leaves unused, not when the cache's user removes it.
+    evicting: Option<Slot>,
+    ghost: GhostList,
+}
+
+const FREQUENCY: u8 = 0b11;
+const MAIN: u8 = 0b100;
+
+impl S3Fifo {
+    /// Creates a policy whose small queue takes up `small_ratio` of the
+    /// cached entries, and whose ghost queue remembers up to `ghost_ratio`
+    /// times as many evicted keys. The paper uses 0.1 and 0.9.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either ratio is negative, or `small_ratio` is above one.
+    pub fn new(small_ratio: f64, ghost_ratio: f64) -> Self {
+        assert!((0.0..=1.0).contains(&small_ratio), "small_ratio must be between 0 and 1");
+        assert!(ghost_ratio >= 0.0, "ghost_ratio must not be negative");
+        S3Fifo {
+            small_ratio,
+            ghost_ratio,
+            small_len: 0,
+            main_len: 0,
+            main: None,
+            evicting: None,
+            ghost: GhostList::with_capacity(0),
+        }
+    }
+
+    /// The number of entries in the small queue.
+    pub fn small_len(&self) -> usize {
+        self.small_len
+    }
+
+    /// The number of entries in the main queue.
+    pub fn main_len(&self) -> usize {
+        self.main_len
+    }
+
+    /// The number of evicted keys remembered in the ghost queue.
+    pub fn ghost_len(&self) -> usize {
+        self.ghost.len()
+    }
+}
+
+impl Default for S3Fifo {
+    fn default() -> Self {
+        S3Fifo::new(0.1, 0.9)
+    }
+}
+
+impl EvictionPolicy for S3Fifo {
+    fn on_insert<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        if !self.ghost.remove(order.hash(slot)) {
+            self.small_len += 1;
+            return;
+        }
+
+        match self.main {
+            Some(head) => order.move_before(slot, head),
+            None => order.move_to_back(slot),
+        }
+        order.set_flags(slot, MAIN);
+        self.main = Some(slot);
+        self.main_len += 1;
+    }
+
+    fn on_access<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        let flags = order.flags(slot);
+        if flags & FREQUENCY < FREQUENCY {
+            order.set_flags(slot, flags + 1);
+        }
+    }
+
+    fn on_remove<K, V>(&mut self, order: &mut Order<'_, K, V>, slot: Slot) {
+        let evicting = self.evicting.take() == Some(slot);
+        if order.flags(slot) & MAIN != 0 {
+            if self.main == Some(slot) {
+                self.main = order.next(slot);
+            }
+            self.main_len -= 1;
+            return;
+        }
+
+        self.small_len -= 1;
+        if evicting {
+            self.ghost.push_front(order.hash(slot));
+            let limit = share(self.ghost_ratio, order.len());
+            while self.ghost.len() > limit {
+                self.ghost.pop_back();
+            }
+        }
+    }
+
+    fn victim<K, V>(&mut self, order: &mut Order<'_, K, V>) -> Slot {
+        let small_target = share(self.small_ratio, order.len());
+        loop {
+            if self.small_len > 0 && (self.small_len >= small_target || self.main_len == 0) {
+                // The small tail sits right in front of the main queue.
+                let tail = match self.main {
+                    Some(head) => order.prev(head).unwrap(),
+                    None => order.back().unwrap(),
+                };
+                if order.flags(tail) & FREQUENCY == 0 {
+                    self.evicting = Some(tail);
+                    return tail;
+                }
+                // Promoting the tail only moves the boundary.
+                order.set_flags(tail, MAIN);
+                self.main = Some(tail);
+                self.small_len -= 1;
+                self.main_len += 1;
+            } else {
+                let tail = order.back().unwrap();
+                let flags = order.flags(tail);
+                if flags & FREQUENCY == 0 {
+                    self.evicting = Some(tail);
+                    return tail;
+                }
+                order.set_flags(tail, flags - 1);
+                if self.main == Some(tail) {
+                    // The only entry in the main queue stays where it is.
+                    continue;
+                }
+                order.move_before(tail, self.main.unwrap());
+                self.main = Some(tail);
+            }
+        }
+    }
+
+    fn victim_except<K, V>(&mut self, order: &mut Order<'_, K, V>, keep: Slot) -> Slot {
+        let mut slot = self.victim(order);
+        if slot == keep {
+            slot = next_to(order, keep);
+            self.evicting = Some(slot);
+        }
+        slot
+    }
+
+    fn on_relocate<F: Fn(Slot) -> Slot>(&mut self, relocated: F) {
+        self.main = self.main.map(relocated);
+    }
+
+    fn on_clear(&mut self) {
+        self.small_len = 0;
+        self.main_len = 0;
+        self.main = None;
+        self.evicting = None;
+        self.ghost.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cache, S3FifoCache, SieveCache, TinyLfuCache, TwoQueueCache};
+
+    fn keys<P: EvictionPolicy>(cache: &Cache<u32, u32, P>) -> Vec<u32> {
+        cache.keys().copied().collect()
+    }
+
+    #[test]
+    fn test_fifo_ignores_hits() {
+        let mut cache: Cache<u32, u32, Fifo> = Cache::new(2);
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        assert_eq!(cache.get(&1), Some(&1));
+
+        cache.insert(3, 3);
+        assert_eq!(keys(&cache), [3, 2]);
+    }
+
+    #[test]
+    fn test_mru_evicts_most_recent() {
+        let mut cache: Cache<u32, u32, Mru> = Cache::new(2);
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        cache.get(&1);
+
+        cache.insert(3, 3);
+        assert_eq!(keys(&cache), [3, 2]);
+    }
+
+    #[test]
+    fn test_clock_gives_second_chance() {
+        let mut cache: Cache<u32, u32, Clock> = Cache::new(3);
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        cache.insert(3, 3);
+        cache.get(&1);
+
+        cache.insert(4, 4);
+        assert!(cache.contains(&1));
+        assert!(!cache.contains(&2));
+
+        // 1 went round again behind 3, and has used up its second chance.
+        cache.insert(5, 5);
+        assert_eq!(keys(&cache), [5, 4, 1]);
+        cache.insert(6, 6);
+        assert_eq!(keys(&cache), [6, 5, 4]);
+    }
+
+    fn slru(capacity: usize, protected_ratio: f64) -> Cache<u32, u32, Slru> {
+        Cache::with_policy(capacity, Slru::new(protected_ratio))
+    }
+
+    #[test]
+    fn test_slru_new_entries_are_probationary() {
+        let mut cache = slru(3, 0.67);
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        cache.get(&1);
+        cache.insert(3, 3);
+        assert_eq!(keys(&cache), [1, 3, 2]);
+        assert_eq!(cache.policy().protected_len(), 1);
+
+        cache.insert(4, 4);
+        assert_eq!(keys(&cache), [1, 4, 3]);
+    }
+
+    #[test]
+    fn test_slru_survives_scan() {
+        let mut cache = slru(4, 0.5);
+        for key in 1..=4 {
+            cache.insert(key, key);
+        }
+        cache.get(&1);
+        cache.get(&2);
+
+        for key in 100..200 {
+            cache.insert(key, key);
+        }
+        assert!(cache.contains(&1));
+        assert!(cache.contains(&2));
+        assert_eq!(keys(&cache), [2, 1, 199, 198]);
+    }
+
+    #[test]
+    fn test_slru_demotes_protected_overflow() {
+        let mut cache = slru(4, 0.5);
+        for key in 1..=4 {
+            cache.insert(key, key);
+        }
+        cache.get(&1);
+        cache.get(&2);
+        cache.get(&3);
+        assert_eq!(cache.policy().protected_len(), 2);
+        // 1 was demoted to the front of the probationary segment.
+        assert_eq!(keys(&cache), [3, 2, 1, 4]);
+
+        cache.insert(5, 5);
+        assert_eq!(keys(&cache), [3, 2, 5, 1]);
+        cache.insert(6, 6);
+        assert_eq!(keys(&cache), [3, 2, 6, 5]);
+    }
+
+    #[test]
+    fn test_slru_remove_and_resize() {
+        let mut cache = slru(4, 0.5);
+        for key in 1..=4 {
+            cache.insert(key, key);
+        }
+        cache.get(&4);
+        cache.remove(&3);
+        cache.remove(&4);
+        assert_eq!(cache.policy().protected_len(), 0);
+        assert_eq!(keys(&cache), [2, 1]);
+
+        cache.get(&1);
+        cache.insert(7, 7);
+        assert_eq!(cache.resize(2).into_vec(), [(2, 2)]);
+        assert_eq!(keys(&cache), [1, 7]);
+        cache.insert(8, 8);
+        assert_eq!(keys(&cache), [1, 8]);
+
+        cache.drain();
+        cache.insert(9, 9);
+        cache.insert(10, 10);
+        assert_eq!(keys(&cache), [10, 9]);
+        assert_eq!(cache.policy().protected_len(), 0);
+    }
+
+    #[test]
+    fn test_slru_protected_share_follows_resize() {
+        let mut cache: crate::SlruCache<u32, u32> = crate::SlruCache::new(10);
+        assert_eq!(cache.policy().protected_ratio(), 0.8);
+        for key in 0..10 {
+            cache.insert(key, key);
+        }
+        for key in 0..10 {
+            cache.get(&key);
+        }
+        assert_eq!(cache.policy().protected_len(), 8);
+
+        // Shrinking evicts the probationary entries and the oldest
+        // protected ones, and the next hit demotes down to the new share.
+        cache.resize(5);
+        assert_eq!(cache.policy().protected_len(), 5);
+        cache.get(&9);
+        assert_eq!(cache.policy().protected_len(), 4);
+        assert_eq!(keys(&cache), [9, 8, 7, 6, 5]);
+    }
+
+    #[test]
+    fn test_slru_protected_limit_follows_capacity() {
+        // A cache that is still filling up protects up to its share of the
+        // capacity, not of the entries cached so far.
+        let mut cache = slru(10, 0.5);
+        for key in 1..=3 {
+            cache.insert(key, key);
+            cache.get(&key);
+        }
+        assert_eq!(cache.policy().protected_len(), 3);
+        assert_eq!(keys(&cache), [3, 2, 1]);
+
+        // The share of a single entry rounds up rather than down to zero.
+        let mut cache = slru(1, 0.8);
+        cache.insert(1, 1);
+        cache.get(&1);
+        assert_eq!(cache.policy().protected_len(), 1);
+        cache.insert(2, 2);
+        assert_eq!(keys(&cache), [2]);
+        assert_eq!(cache.policy().protected_len(), 0);
+    }
+
+    #[test]
+    fn test_two_queue_promotes_from_ghosts() {
+        let mut cache: TwoQueueCache<u32, u32> = TwoQueueCache::new(4);
+        for key in 1..=5 {
+            cache.insert(key, key);
+        }
+        assert_eq!(keys(&cache), [5, 4, 3, 2]);
+        assert_eq!(cache.policy().out_len(), 1);
+
+        // 1 is remembered in A1out, so it goes straight to Am.
+        cache.insert(1, 1);
+        assert_eq!(keys(&cache), [1, 5, 4, 3]);
+        assert_eq!(cache.policy().in_len(), 3);
+
+        // Hits in A1in change nothing.
+        cache.get(&3);
+        cache.insert(6, 6);
+        assert_eq!(keys(&cache), [1, 6, 5, 4]);
+
+        for key in 100..200 {
+            cache.insert(key, key);
+        }
+        assert_eq!(keys(&cache), [1, 199, 198, 197]);
+        assert_eq!(cache.policy().out_len(), 2);
+    }
+
+    #[test]
+    fn test_two_queue_evicts_from_am() {
+        let mut cache = Cache::with_policy(4, TwoQueue::new(0.25, 2.0));
+        for key in 1..=8 {
+            cache.insert(key, key);
+        }
+        for key in 1..=3 {
+            cache.insert(key, key);
+        }
+        assert_eq!(keys(&cache), [3, 2, 1, 8]);
+        assert_eq!(cache.policy().in_len(), 1);
+
+        // A1in is within its share, so the Am tail goes.
+        cache.insert(4, 4);
+        assert_eq!(keys(&cache), [4, 3, 2, 8]);
+        cache.get(&2);
+        cache.insert(9, 9);
+        assert_eq!(keys(&cache), [2, 4, 9, 8]);
+
+        cache.drain();
+        assert_eq!(cache.policy().in_len(), 0);
+        assert_eq!(cache.policy().out_len(), 0);
+        cache.insert(5, 5);
+        cache.insert(6, 6);
+        assert_eq!(keys(&cache), [6, 5]);
+    }
+
+    #[test]
+    fn test_sieve_keeps_visited_in_place() {
+        let mut cache: SieveCache<u32, u32> = SieveCache::new(3);
+        for key in 1..=3 {
+            cache.insert(key, key);
+        }
+        cache.get(&1);
+
+        cache.insert(4, 4);
+        assert_eq!(keys(&cache), [4, 3, 1]);
+        // The hand moved on from 2 to 3, and 1 has lost its visited bit.
+        cache.get(&4);
+        cache.insert(5, 5);
+        assert_eq!(keys(&cache), [5, 4, 1]);
+        // The hand carries on towards the front, past 4 to 5, rather than
+        // starting over at the back.
+        cache.insert(6, 6);
+        assert_eq!(keys(&cache), [6, 4, 1]);
+    }
+
+    /// A straightforward SIEVE over a `Vec` ordered from oldest to newest.
+    struct ReferenceSieve {
+        capacity: usize,
+        queue: Vec<(u32, bool)>,
+        hand: Option<usize>,
+    }
+
+    impl ReferenceSieve {
+        fn position(&self, key: u32) -> Option<usize> {
+            self.queue.iter().position(|&(k, _)| k == key)
+        }
+
+        fn get(&mut self, key: u32) -> bool {
+            match self.position(key) {
+                Some(i) => {
+                    self.queue[i].1 = true;
+                    true
+                }
+                None => false,
+            }
+        }
+
+        fn insert(&mut self, key: u32) -> Option<u32> {
+            if self.get(key) {
+                return None;
+            }
+            let mut evicted = None;
+            if self.queue.len() == self.capacity {
+                let mut i = self.hand.unwrap_or(0);
+                while self.queue[i].1 {
+                    self.queue[i].1 = false;
+                    i = (i + 1) % self.queue.len();
+                }
+                self.hand = Some(i);
+                evicted = Some(self.remove_at(i));
+            }
+            self.queue.push((key, false));
+            evicted
+        }
+
+        fn remove(&mut self, key: u32) {
+            if let Some(i) = self.position(key) {
+                self.remove_at(i);
+            }
+        }
+
+        fn remove_at(&mut self, i: usize) -> u32 {
+            let (key, _) = self.queue.remove(i);
+            self.hand = match self.hand {
+                Some(hand) if hand > i => Some(hand - 1),
+                Some(hand) if hand == i => (i < self.queue.len()).then_some(i),
+                hand => hand,
+            };
+            key
+        }
+    }
+
+    #[test]
+    fn test_sieve_matches_reference() {
+        let mut cache: SieveCache<u32, u32> = SieveCache::new(10);
+        let mut reference = ReferenceSieve { capacity: 10, queue: Vec::new(), hand: None };
+        let mut state = 0x2545_f491_u32;
+        for _ in 0..10_000 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let key = state % 30;
+            match state >> 28 {
+                0 => {
+                    cache.remove(&key);
+                    reference.remove(key);
+                }
+                1..=7 => assert_eq!(cache.get(&key).is_some(), reference.get(key)),
+                _ => {
+                    let evicted = cache.insert(key, key).evicted.into_iter().next();
+                    assert_eq!(evicted.map(|(key, _)| key), reference.insert(key));
+                }
+            }
+            let expected: Vec<_> = reference.queue.iter().rev().map(|&(key, _)| key).collect();
+            assert_eq!(keys(&cache), expected);
+        }
+    }
+
+    #[test]
+    fn test_tiny_lfu_segments() {
+        let mut cache = Cache::with_policy(4, TinyLfu::new(0.25, 0.5));
+        for key in 1..=4 {
+            cache.insert(key, key);
+        }
+        // The window holds the newest entry, the rest are probationary.
+        assert_eq!(keys(&cache), [4, 3, 2, 1]);
+        assert_eq!(cache.policy().window_len(), 1);
+
+        cache.get(&2);
+        cache.get(&1);
+        assert_eq!(cache.policy().protected_len(), 1);
+        // 2 was demoted to the front of the probationary segment again.
+        assert_eq!(keys(&cache), [4, 1, 2, 3]);
+
+        // 4 leaves the window for 5. The demoted 2 has been seen more often
+        // than 4, but only 4 competes, with 3, and it loses the tie.
+        cache.insert(5, 5);
+        assert_eq!(keys(&cache), [5, 1, 2, 3]);
+        // 5 has been seen more often than 3, so it takes its place.
+        cache.get(&5);
+        cache.insert(6, 6);
+        assert_eq!(keys(&cache), [6, 1, 5, 2]);
+
+        cache.remove(&1);
+        cache.get(&2);
+        assert_eq!(keys(&cache), [6, 2, 5]);
+        cache.drain();
+        assert_eq!(cache.policy().window_len(), 0);
+        assert_eq!(cache.policy().protected_len(), 0);
+    }
+
+    #[test]
+    fn test_tiny_lfu_rejects_one_hit_wonders() {
+        let mut cache: TinyLfuCache<u32, u32> = TinyLfuCache::new(8);
+        for _ in 0..8 {
+            for key in 1..=6 {
+                if cache.get(&key).is_none() {
+                    cache.insert(key, key);
+                }
+            }
+        }
+
+        for key in 100..600 {
+            cache.insert(key, key);
+        }
+        for key in 1..=6 {
+            assert!(cache.contains(&key), "lost {key}");
+        }
+        assert_eq!(cache.len(), 8);
+    }
+
+    #[test]
+    fn test_tiny_lfu_keeps_counts_while_filling() {
+        let mut cache: TinyLfuCache<u32, u32> = TinyLfuCache::new(1000);
+        let hash = cache.table.hash(&0);
+        cache.insert(0, 0);
+        for _ in 0..3 {
+            cache.get(&0);
+        }
+
+        // Growing past 64, 128 and 256 entries used to start the sketch
+        // over.
+        for key in 1..300 {
+            cache.insert(key, key);
+        }
+        assert_eq!(cache.policy().sketch.frequency(hash), 4);
+
+        // Resizing to a different sketch size does start over.
+        cache.resize(100);
+        assert_eq!(cache.policy().sketch.frequency(hash), 0);
+    }
+
+    #[test]
+    fn test_s3_fifo_filters_one_hit_wonders() {
+        let mut cache: S3FifoCache<u32, u32> = S3FifoCache::new(10);
+        for key in 1..=11 {
+            cache.insert(key, key);
+        }
+        assert!(!cache.contains(&1));
+        assert_eq!(cache.policy().ghost_len(), 1);
+
+        // 1 is remembered, so it skips the small queue.
+        cache.insert(1, 1);
+        assert_eq!(keys(&cache), [11, 10, 9, 8, 7, 6, 5, 4, 3, 1]);
+        assert_eq!(cache.policy().main_len(), 1);
+
+        // 3 was hit, so it moves to the main queue instead of 4.
+        cache.get(&3);
+        cache.insert(12, 12);
+        assert_eq!(keys(&cache), [12, 11, 10, 9, 8, 7, 6, 5, 3, 1]);
+        assert_eq!(cache.policy().main_len(), 2);
+
+        for key in 100..200 {
+            cache.insert(key, key);
+        }
+        assert!(cache.contains(&1));
+        assert!(cache.contains(&3));
+    }
+
+    #[test]
+    fn test_s3_fifo_main_queue() {
+        let mut cache = Cache::with_policy(4, S3Fifo::new(0.5, 1.0));
+        for key in 1..=4 {
+            cache.insert(key, key);
+        }
+        cache.get(&1);
+        cache.get(&2);
+        cache.insert(5, 5);
+        assert_eq!(keys(&cache), [5, 4, 2, 1]);
+        assert_eq!(cache.policy().main_len(), 2);
+
+        cache.get(&1);
+        cache.get(&1);
+        cache.insert(6, 6);
+        cache.insert(3, 3);
+        assert_eq!(keys(&cache), [6, 3, 2, 1]);
+        assert_eq!(cache.policy().small_len(), 1);
+
+        // The small queue is below its share, so the main queue evicts. 1
+        // was hit and goes round again, 2 has used up its hit.
+        cache.insert(7, 7);
+        assert_eq!(keys(&cache), [7, 6, 1, 3]);
+        assert_eq!(cache.policy().ghost_len(), 2);
+
+        cache.drain();
+        assert_eq!(cache.policy().main_len(), 0);
+        assert_eq!(cache.policy().ghost_len(), 0);
+    }
+
+    #[test]
+    fn test_custom_policy() {
+        /// Evicts the entry with the smallest slot index.
+        #[derive(Default)]
+        struct LowestSlot;
+
+        impl EvictionPolicy for LowestSlot {
+            fn victim<K, V>(&mut self, order: &mut Order<'_, K, V>) -> Slot {
+                let mut best = order.front().unwrap();
+                let mut current = order.next(best);
+                while let Some(slot) = current {
+                    if slot.index() < best.index() {
+                        best = slot;
+                    }
+                    current = order.next(slot);
+                }
+                best
+            }
+        }
+
+        let mut cache: Cache<u32, u32, LowestSlot> = Cache::new(2);
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        cache.insert(3, 3);
+        assert_eq!(keys(&cache), [3, 2]);
+    }
+
+    #[test]
+    fn test_with_policy_and_weigher() {
+        let weigher = |_: &u32, value: &u32| *value as usize;
+        let mut cache = Cache::with_policy_and_weigher(5, Fifo, weigher);
+        cache.insert(1, 2);
+        cache.insert(2, 2);
+        cache.get(&1);
+        cache.insert(3, 2);
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [3, 2]);
+        assert_eq!(cache.weight(), 4);
+    }
+
+    #[test]
+    fn test_weighted_fifo_spares_replaced_entry() {
+        let weigher = |_: &u32, value: &u32| *value as usize;
+        let mut cache = Cache::with_policy_and_weigher(5, Fifo, weigher);
+        cache.insert(1, 2);
+        cache.insert(2, 2);
+        // 1 is the victim, but it is the entry being replaced.
+        assert_eq!(cache.insert(1, 4).evicted.into_vec(), [(2, 2)]);
+        assert_eq!(cache.weight(), 4);
+    }
+
+    #[test]
+    fn test_weighted_mru_spares_grown_entry() {
+        let weigher = |_: &u32, value: &u32| *value as usize;
+        let mut cache = Cache::with_policy_and_weigher(5, Mru, weigher);
+        for key in 1..=3 {
+            cache.insert(key, 1);
+        }
+        assert_eq!(cache.insert(3, 4).evicted.into_vec(), [(2, 1)]);
+        assert_eq!(cache.weight(), 5);
+
+        let mut value = cache.get_mut_weighed(&1).unwrap();
+        *value = 2;
+        assert_eq!(value.commit().into_vec(), [(3, 4)]);
+        assert_eq!(cache.weight(), 2);
+    }
+
+    #[test]
+    fn test_two_queue_spared_entry_is_not_a_ghost() {
+        let weigher = |_: &u32, value: &u32| *value as usize;
+        let mut cache = Cache::with_policy_and_weigher(5, TwoQueue::default(), weigher);
+        cache.insert(1, 1);
+        cache.insert(2, 1);
+        assert_eq!(cache.insert(1, 5).evicted.into_vec(), [(2, 1)]);
+        assert_eq!(cache.policy().out_len(), 1);
+
+        // Removing the spared entry explicitly does not remember it.
+        cache.remove(&1);
+        assert_eq!(cache.policy().out_len(), 1);
+        cache.insert(1, 1);
+        assert_eq!(cache.policy().in_len(), 1);
+    }
+
+    #[test]
+    fn test_s3_fifo_remembers_entry_evicted_around_kept_one() {
+        let weigher = |_: &u32, value: &u32| *value as usize;
+        let mut cache = Cache::with_policy_and_weigher(5, S3Fifo::default(), weigher);
+        cache.insert(1, 1);
+        cache.insert(2, 1);
+        // 1 is the small queue's tail, but it is the entry that grew.
+        let mut value = cache.peek_mut_weighed(&1).unwrap();
+        *value = 5;
+        assert_eq!(value.commit().into_vec(), [(2, 1)]);
+        assert_eq!(cache.policy().ghost_len(), 1);
+
+        // Removing the spared entry explicitly does not remember it.
+        cache.remove(&1);
+        assert_eq!(cache.policy().ghost_len(), 1);
+    }
+}
@@ -0,0 +1,220 @@
+//! Approximate access frequencies for TinyLFU admission.
+//!
+//! A Count-Min Sketch of 4-bit counters, fronted by a doorkeeper bloom
+//! filter that absorbs the first access to each key so one-hit wonders never
+//! reach the counters. Once the number of recorded accesses reaches ten
+//! times the tracked capacity, every counter is halved and the doorkeeper is
+//! cleared, so old popularity fades.
+
+use std::fmt;
+
+const ROWS: usize = 4;
+
+// Odd multipliers that spread the cache's key hash over each row.
+const SEEDS: [u64; ROWS] = [
+    0x9e37_79b9_7f4a_7c15,
+    0xc2b2_ae3d_27d4_eb4f,
+    0x1656_67b1_9e37_79f9,
+    0xbf58_476d_1ce4_e5b9,
+];
+
+// Mixes the hash again for the doorkeeper, whose bits would otherwise come
+// from the same hash bits that pick the table's buckets.
+const DOORKEEPER_SEED: u64 = 0x94d0_49bb_1331_11eb;
+
+const RESET_MASK: u64 = 0x7777_7777_7777_7777;
+
+// Caps each of the two tables at 8 MiB, however large the capacity. A
+// weighted cache passes its total weight, which may be far more than the
+// number of keys.
+const MAX_WORDS: usize = 1 << 20;
+
+pub(crate) struct FrequencySketch {
+    // Sixteen counters per word, four for each row.
+    counters: Vec<u64>,
+    doorkeeper: Vec<u64>,
+    capacity: usize,
+    additions: usize,
+}
+
+impl fmt::Debug for FrequencySketch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrequencySketch")
+            .field("capacity", &self.capacity)
+            .field("additions", &self.additions)
+            .finish()
+    }
+}
+
+impl FrequencySketch {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        let words = Self::words_for(capacity);
+        FrequencySketch {
+            counters: vec![0; words],
+            doorkeeper: vec![0; words],
+            capacity,
+            additions: 0,
+        }
+    }
+
+    /// Sizes the sketch for `capacity` keys. The counts are kept if the
+    /// tables stay the same size, and start over otherwise.
+    pub(crate) fn resize(&mut self, capacity: usize) {
+        if Self::words_for(capacity) == self.counters.len() {
+            self.capacity = capacity;
+        } else {
+            *self = FrequencySketch::with_capacity(capacity);
+        }
+    }
+
+    fn words_for(capacity: usize) -> usize {
+        capacity.clamp(1, MAX_WORDS).next_power_of_two()
+    }
+
+    /// The estimated number of recent accesses to `hash`, at most 16.
+    pub(crate) fn frequency(&self, hash: u64) -> u8 {
+        let count = (0..ROWS).map(|row| self.counter(hash, row)).min().unwrap();
+        count + self.doorkeeper_contains(hash) as u8
+    }
+
+    pub(crate) fn increment(&mut self, hash: u64) {
+        if self.doorkeeper_insert(hash) {
+            // First sighting since the last reset.
+        } else {
+            let mut added = false;
+            for row in 0..ROWS {
+                added |= self.increment_counter(hash, row);
+            }
+            if !added {
+                return;
+            }
+        }
+
+        self.additions += 1;
+        if self.additions >= 10 * self.capacity.max(1) {
+            self.reset();
+        }
+    }
+
+    fn reset(&mut self) {
+        for word in &mut self.counters {
+            *word = (*word >> 1) & RESET_MASK;
+        }
+        self.doorkeeper.fill(0);
+        self.additions /= 2;
+    }
+
+    /// The word and bit offset of `hash`'s counter in `row`.
+    fn position(&self, hash: u64, row: usize) -> (usize, u32) {
+        let mixed = hash.wrapping_mul(SEEDS[row]);
+        let word = (mixed >> 32) as usize & (self.counters.len() - 1);
+        let counter = row as u32 * 4 + (mixed >> 62) as u32;
+        (word, counter * 4)
+    }
+
+    fn counter(&self, hash: u64, row: usize) -> u8 {
+        let (word, shift) = self.position(hash, row);
+        (self.counters[word] >> shift) as u8 & 0xf
+    }
+
+    fn increment_counter(&mut self, hash: u64, row: usize) -> bool {
+        let (word, shift) = self.position(hash, row);
+        if (self.counters[word] >> shift) & 0xf == 0xf {
+            return false;
+        }
+        self.counters[word] += 1 << shift;
+        true
+    }
+
+    /// The two doorkeeper bits for `hash`.
+    fn bits(&self, hash: u64) -> [usize; 2] {
+        let mask = self.doorkeeper.len() * 64 - 1;
+        let mixed = hash.wrapping_mul(DOORKEEPER_SEED);
+        let mixed = mixed ^ (mixed >> 32);
+        [mixed as usize & mask, (mixed >> 32) as usize & mask]
+    }
+
+    fn doorkeeper_contains(&self, hash: u64) -> bool {
+        self.bits(hash)
+            .iter()
+            .all(|&bit| self.doorkeeper[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Sets the bits for `hash`, returning whether any was unset.
+    fn doorkeeper_insert(&mut self, hash: u64) -> bool {
+        let mut inserted = false;
+        for bit in self.bits(hash) {
+            let word = &mut self.doorkeeper[bit / 64];
+            inserted |= *word & (1 << (bit % 64)) == 0;
+            *word |= 1 << (bit % 64);
+        }
+        inserted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fixed stand-in for the cache's hasher, so collisions are stable.
+    fn hash(key: u64) -> u64 {
+        let mut h = key.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^ (h >> 31)
+    }
+
+    #[test]
+    fn test_counts_accesses() {
+        let mut sketch = FrequencySketch::with_capacity(64);
+        let (hot, cold) = (hash(1), hash(2));
+
+        assert_eq!(sketch.frequency(hot), 0);
+        sketch.increment(hot);
+        assert_eq!(sketch.frequency(hot), 1);
+        for _ in 0..4 {
+            sketch.increment(hot);
+        }
+        sketch.increment(cold);
+        assert_eq!(sketch.frequency(hot), 5);
+        assert_eq!(sketch.frequency(cold), 1);
+
+        for _ in 0..100 {
+            sketch.increment(hot);
+        }
+        assert_eq!(sketch.frequency(hot), 16);
+    }
+
+    #[test]
+    fn test_ages_counters() {
+        let mut sketch = FrequencySketch::with_capacity(16);
+        let hot = hash(0);
+        for _ in 0..9 {
+            sketch.increment(hot);
+        }
+        assert_eq!(sketch.frequency(hot), 9);
+
+        // Enough one-off keys to trigger a reset, which halves the counters
+        // and clears the doorkeeper.
+        for key in 1..152 {
+            sketch.increment(hash(key));
+        }
+        assert_eq!(sketch.frequency(hot), 4);
+    }
+
+    #[test]
+    fn test_resize_keeps_counts_at_same_size() {
+        let mut sketch = FrequencySketch::with_capacity(100);
+        sketch.increment(hash(1));
+        sketch.increment(hash(1));
+
+        sketch.resize(120);
+        assert_eq!(sketch.frequency(hash(1)), 2);
+        sketch.resize(1000);
+        assert_eq!(sketch.frequency(hash(1)), 0);
+        assert_eq!(sketch.counters.len(), 1024);
+
+        sketch.resize(usize::MAX);
+        assert_eq!(sketch.counters.len(), MAX_WORDS);
+    }
+}
@@ -0,0 +1,135 @@
+//! Contiguous storage for cache nodes, addressed by `u32` indices.
+
+use std::ops::{Index, IndexMut};
+
+use crate::list::NIL;
+
+enum Slot<T> {
+    Occupied(T),
+    // Index of the next vacant slot, or `NIL`.
+    Vacant(u32),
+}
+
+/// A `Vec` of slots where removed slots are chained into a free list and
+/// handed out again by the next `insert`, so indices stay stable and the
+/// backing storage never shrinks behind our back.
+pub(crate) struct Slab<T> {
+    slots: Vec<Slot<T>>,
+    free: u32,
+    len: usize,
+}
+
+impl<T> Slab<T> {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Slab {
+            slots: Vec::with_capacity(capacity),
+            free: NIL,
+            len: 0,
+        }
+    }
+
+    /// Number of slots the slab can hold without reallocating.
+    pub(crate) fn capacity(&self) -> usize {
+        self.slots.capacity()
+    }
+
+    /// Makes room for `capacity` slots in total.
+    pub(crate) fn grow_to(&mut self, capacity: usize) {
+        self.slots.reserve_exact(capacity.saturating_sub(self.slots.len()));
+    }
+
+    /// One past the highest slot index handed out so far.
+    pub(crate) fn end(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Number of slots that are allocated but currently unused.
+    #[cfg(test)]
+    pub(crate) fn vacant(&self) -> usize {
+        self.slots.len() - self.len
+    }
+
+    /// The value at `index`, or `None` if that slot is vacant or was never
+    /// handed out.
+    pub(crate) fn get(&self, index: u32) -> Option<&T> {
+        match self.slots.get(index as usize)? {
+            Slot::Occupied(value) => Some(value),
+            Slot::Vacant(_) => None,
+        }
+    }
+
+    pub(crate) fn insert(&mut self, value: T) -> u32 {
+        self.len += 1;
+
+        if self.free != NIL {
+            let index = self.free;
+            match std::mem::replace(&mut self.slots[index as usize], Slot::Occupied(value)) {
+                Slot::Vacant(next) => self.free = next,
+                Slot::Occupied(_) => unreachable!("free list points at an occupied slot"),
+            }
+            index
+        } else {
+            let index = self.slots.len();
+            assert!(index < NIL as usize, "slab index overflow");
+            self.slots.push(Slot::Occupied(value));
+            index as u32
+        }
+    }
+
+    pub(crate) fn remove(&mut self, index: u32) -> T {
+        match std::mem::replace(&mut self.slots[index as usize], Slot::Vacant(self.free)) {
+            Slot::Occupied(value) => {
+                self.free = index;
+                self.len -= 1;
+                value
+            }
+            Slot::Vacant(_) => unreachable!("removed a vacant slot"),
+        }
+    }
+}
+
+/// Raw view of a slab's slots, for handing out `&mut` borrows of several
+/// distinct slots at once.
+pub(crate) struct RawSlab<T> {
+    slots: *mut Slot<T>,
+}
+
+impl<T> Slab<T> {
+    pub(crate) fn raw(&mut self) -> RawSlab<T> {
+        RawSlab { slots: self.slots.as_mut_ptr() }
+    }
+}
+
+impl<T> RawSlab<T> {
+    /// # Safety
+    ///
+    /// The slab must outlive `'a` and must not be accessed in any other way
+    /// during `'a`. `index` must be an occupied slot that is not borrowed
+    /// elsewhere.
+    pub(crate) unsafe fn get_mut<'a>(&self, index: u32) -> &'a mut T {
+        match unsafe { &mut *self.slots.add(index as usize) } {
+            Slot::Occupied(value) => value,
+            Slot::Vacant(_) => unreachable!("accessed a vacant slot"),
+        }
+    }
+}
+
+impl<T> Index<u32> for Slab<T> {
+    type Output = T;
+
+    fn index(&self, index: u32) -> &T {
+        match &self.slots[index as usize] {
+            Slot::Occupied(value) => value,
+            Slot::Vacant(_) => unreachable!("accessed a vacant slot"),
+        }
+    }
+}
+
+impl<T> IndexMut<u32> for Slab<T> {
+    fn index_mut(&mut self, index: u32) -> &mut T {
+        match &mut self.slots[index as usize] {
+            Slot::Occupied(value) => value,
+            Slot::Vacant(_) => unreachable!("accessed a vacant slot"),
+        }
+    }
+}
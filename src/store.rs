@@ -0,0 +1,106 @@
+//! Keyed node storage for the caches that keep their own lists.
+//!
+//! Pairs a [`Table`] with the [`Slab`] it indexes, and does the lookups and
+//! removals those caches have in common. Each cache still links the nodes
+//! into its own lists, so it unlinks a node before removing it here.
+
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+use crate::slab::Slab;
+use crate::table::Table;
+
+/// A node in a [`Store`].
+pub(crate) trait Keyed {
+    type Key;
+    type Value;
+
+    fn hash(&self) -> u64;
+
+    /// The cached key and value, or `None` for a node that only remembers
+    /// an evicted key by its hash.
+    fn entry(&self) -> Option<(&Self::Key, &Self::Value)>;
+
+    fn value_mut(&mut self) -> Option<&mut Self::Value>;
+
+    fn into_entry(self) -> Option<(Self::Key, Self::Value)>;
+}
+
+pub(crate) struct Store<T> {
+    table: Table,
+    pub(crate) nodes: Slab<T>,
+}
+
+impl<T: Keyed> Store<T> {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Store {
+            table: Table::with_capacity(capacity),
+            nodes: Slab::with_capacity(capacity),
+        }
+    }
+
+    /// The number of nodes, including those that only remember a key.
+    pub(crate) fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub(crate) fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.table.hash(key)
+    }
+
+    /// Finds the node cached under `key`, or the node remembering it.
+    pub(crate) fn find<Q>(&self, hash: u64, key: &Q) -> Option<u32>
+        where T::Key: Borrow<Q>, Q: Eq + ?Sized,
+    {
+        self.table.find(hash, |index| {
+            let node = &self.nodes[index];
+            match node.entry() {
+                Some((k, _)) => k.borrow() == key,
+                None => node.hash() == hash,
+            }
+        })
+    }
+
+    /// Finds the node caching `key`.
+    pub(crate) fn index_of<Q>(&self, key: &Q) -> Option<u32>
+        where T::Key: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.find(self.hash(key), key)
+            .filter(|&index| self.nodes[index].entry().is_some())
+    }
+
+    pub(crate) fn contains<Q>(&self, key: &Q) -> bool
+        where T::Key: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.index_of(key).is_some()
+    }
+
+    pub(crate) fn peek<Q>(&self, key: &Q) -> Option<&T::Value>
+        where T::Key: Borrow<Q>, Q: Hash + Eq + ?Sized,
+    {
+        self.index_of(key).map(|index| self.value(index))
+    }
+
+    /// The value of a node known to cache an entry.
+    pub(crate) fn value(&self, index: u32) -> &T::Value {
+        self.nodes[index].entry().unwrap().1
+    }
+
+    pub(crate) fn value_mut(&mut self, index: u32) -> &mut T::Value {
+        self.nodes[index].value_mut().unwrap()
+    }
+
+    pub(crate) fn insert(&mut self, node: T) -> u32 {
+        let hash = node.hash();
+        let index = self.nodes.insert(node);
+        let nodes = &self.nodes;
+        self.table.insert(hash, index, |index| nodes[index].hash());
+        index
+    }
+
+    /// Removes a node that is no longer on any list.
+    pub(crate) fn remove(&mut self, index: u32) -> T {
+        self.table.remove(self.nodes[index].hash(), index);
+        self.nodes.remove(index)
+    }
+}
@@ -0,0 +1,63 @@
+//! Hash index from keys to slab indices.
+//!
+//! The table only stores `u32` indices. Keys stay in the slab, so lookups
+//! compare against the key stored in the node and each key is kept once.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use hashbrown::HashTable;
+
+pub(crate) struct Table {
+    table: HashTable<u32>,
+    hasher: RandomState,
+}
+
+impl Table {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Table {
+            table: HashTable::with_capacity(capacity),
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.table.clear();
+    }
+
+    pub(crate) fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.hasher.hash_one(key)
+    }
+
+    pub(crate) fn find(&self, hash: u64, mut eq: impl FnMut(u32) -> bool) -> Option<u32> {
+        self.table.find(hash, |&index| eq(index)).copied()
+    }
+
+    /// Adds `index` under `hash`. `hash_of` must return the stored hash of
+    /// any index already in the table, in case the table has to grow.
+    pub(crate) fn insert(&mut self, hash: u64, index: u32, hash_of: impl Fn(u32) -> u64) {
+        self.table.insert_unique(hash, index, |&index| hash_of(index));
+    }
+
+    pub(crate) fn reserve(&mut self, additional: usize, hash_of: impl Fn(u32) -> u64) {
+        self.table.reserve(additional, |&index| hash_of(index));
+    }
+
+    pub(crate) fn shrink_to(&mut self, min_capacity: usize, hash_of: impl Fn(u32) -> u64) {
+        self.table.shrink_to(min_capacity, |&index| hash_of(index));
+    }
+
+    pub(crate) fn remove(&mut self, hash: u64, index: u32) {
+        if let Ok(entry) = self.table.find_entry(hash, |&other| other == index) {
+            entry.remove();
+        }
+    }
+}
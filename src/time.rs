@@ -0,0 +1,92 @@
+//! Time sources for entry expiration.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Tells a cache the current time when it checks or sets expiration
+/// deadlines.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// Reads the system's monotonic clock. This is the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to, for testing expiration without
+/// sleeping.
+///
+/// Clones share the same time, so a test can hand one clone to a cache and
+/// keep another to [`advance`](Self::advance) it.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    start: Instant,
+    // Nanoseconds advanced since `start`.
+    elapsed: Arc<AtomicU64>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        MockClock { start: Instant::now(), elapsed: Arc::new(AtomicU64::new(0)) }
+    }
+
+    /// Moves the time forward by `duration`. The clock stops at about 584
+    /// years past its creation rather than wrapping around.
+    pub fn advance(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        let _ = self.elapsed.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |elapsed| {
+            Some(elapsed.saturating_add(nanos))
+        });
+    }
+
+    /// How far the clock has been advanced since it was created.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock_clones_share_time() {
+        let clock = MockClock::new();
+        let shared = clock.clone();
+        let before = shared.now();
+        assert_eq!(clock.now(), before);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(shared.now() - before, Duration::from_secs(5));
+        assert_eq!(shared.elapsed(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_mock_clock_saturates() {
+        let clock = MockClock::new();
+        let before = clock.now();
+        clock.advance(Duration::MAX);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(clock.elapsed(), Duration::from_nanos(u64::MAX));
+        assert!(clock.now() > before);
+    }
+}
@@ -0,0 +1,102 @@
+//! Entry weights for cost-bounded caches.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use crate::policy::EvictionPolicy;
+use crate::{Cache, Evicted};
+
+/// Computes how much of a cache's capacity an entry uses.
+///
+/// Weights are taken when an entry is inserted or its value is replaced, and
+/// again when a [`ValueMut`] guard is dropped.
+pub trait Weigher<K, V> {
+    fn weight(&self, key: &K, value: &V) -> usize;
+}
+
+/// Gives every entry a weight of one, so the capacity is an entry count.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UnitWeigher;
+
+impl<K, V> Weigher<K, V> for UnitWeigher {
+    fn weight(&self, _key: &K, _value: &V) -> usize {
+        1
+    }
+}
+
+impl<K, V, F> Weigher<K, V> for F
+    where F: Fn(&K, &V) -> usize
+{
+    fn weight(&self, key: &K, value: &V) -> usize {
+        self(key, value)
+    }
+}
+
+/// Mutable access to a cached value, returned by [`Cache::get_mut_weighed`]
+/// and [`Cache::peek_mut_weighed`].
+///
+/// When the guard is dropped the entry is weighed again and other entries
+/// are evicted until the cache fits. Those entries are dropped; call
+/// [`commit`](Self::commit) instead to get them back. The guarded entry
+/// itself is never evicted here, even if it has grown past the capacity;
+/// the next insertion takes care of that.
+pub struct ValueMut<'a, K, V, P, W>
+    where P: EvictionPolicy, W: Weigher<K, V>
+{
+    cache: &'a mut Cache<K, V, P, W>,
+    index: u32,
+}
+
+impl<'a, K, V, P, W> ValueMut<'a, K, V, P, W>
+    where P: EvictionPolicy, W: Weigher<K, V>
+{
+    pub(crate) fn new(cache: &'a mut Cache<K, V, P, W>, index: u32) -> Self {
+        ValueMut { cache, index }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.cache.entries[self.index].key
+    }
+
+    /// Weighs the entry again and returns the entries evicted to make it
+    /// fit, least recently used first.
+    pub fn commit(self) -> Evicted<K, V> {
+        let evicted = self.cache.reweigh(self.index);
+        std::mem::forget(self);
+        evicted
+    }
+}
+
+impl<K, V, P, W> Deref for ValueMut<'_, K, V, P, W>
+    where P: EvictionPolicy, W: Weigher<K, V>
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.cache.entries[self.index].value
+    }
+}
+
+impl<K, V, P, W> DerefMut for ValueMut<'_, K, V, P, W>
+    where P: EvictionPolicy, W: Weigher<K, V>
+{
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.cache.entries[self.index].value
+    }
+}
+
+impl<K, V, P, W> fmt::Debug for ValueMut<'_, K, V, P, W>
+    where V: fmt::Debug, P: EvictionPolicy, W: Weigher<K, V>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ValueMut").field(&**self).finish()
+    }
+}
+
+impl<K, V, P, W> Drop for ValueMut<'_, K, V, P, W>
+    where P: EvictionPolicy, W: Weigher<K, V>
+{
+    fn drop(&mut self) {
+        self.cache.reweigh(self.index);
+    }
+}